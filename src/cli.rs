@@ -0,0 +1,64 @@
+use clap::{ArgAction, Args, Parser, Subcommand};
+use crate::py_automation::FleetMode;
+
+/// Аргументы командной строки bombie-bot
+#[derive(Debug, Parser)]
+#[command(name = "bombie-bot", about = "Bombie automation bot", disable_version_flag = true)]
+pub struct Cli {
+    /// Версии bombie-bot, Python из venv, Playwright и установленных браузеров
+    #[arg(short = 'V', long, action = ArgAction::SetTrue)]
+    pub version: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Запуск автоматизации (по умолчанию, если команда не указана)
+    Run(RunArgs),
+    /// Настроить окружение и вывести диагностику Python: sys.path, PYTHONPATH, VIRTUAL_ENV,
+    /// версии установленных пакетов и статус импорта каждой зависимости
+    Doctor,
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct RunArgs {
+    /// Инициализировать только указанное устройство (например ios_device) и запустить автоматизацию на нем
+    #[arg(long)]
+    pub device: Option<String>,
+
+    /// Только подготовить окружение (venv, зависимости, браузеры) и выйти без эмуляции и автоматизации.
+    /// То же самое включает PREPARE_ONLY=true
+    #[arg(long)]
+    pub prepare: bool,
+
+    /// Проверить окружение (venv, зависимости, браузеры Playwright, импорты) и выйти с отчетом:
+    /// код 0 при успехе, иначе 1. Кэш Playwright не создается, браузеры и автоматизация не запускаются.
+    /// То же самое включает VERIFY_ONLY=true
+    #[arg(long, conflicts_with_all = ["prepare", "fleet"])]
+    pub verify_only: bool,
+
+    /// Запустить автоматизацию параллельно на всех инициализированных устройствах
+    #[arg(long, conflicts_with = "device")]
+    pub fleet: bool,
+
+    /// Поведение парка при ошибке устройства: best-effort (по умолчанию) или fail-fast.
+    /// Без флага используется FLEET_MODE
+    #[arg(long, requires = "fleet")]
+    pub fleet_mode: Option<FleetMode>,
+}
+
+impl Cli {
+    pub fn is_doctor(&self) -> bool {
+        matches!(self.command, Some(Command::Doctor))
+    }
+
+    /// Аргументы запуска; без подкоманды используется `run` с параметрами по умолчанию
+    pub fn run_args(&self) -> RunArgs {
+        match &self.command {
+            Some(Command::Run(args)) => args.clone(),
+            Some(Command::Doctor) | None => RunArgs::default(),
+        }
+    }
+}
@@ -1,5 +1,38 @@
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::fs;
+use anyhow::{Result, anyhow};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::sync::watch;
+use crate::errors::ReadinessTimeout;
+use std::collections::HashMap;
+use serde_json::{Map, Value};
+use crate::emulation::{DensityBucket, DohConfig, LocaleProfile, LocaleStrategy};
+use crate::utils::env_flag;
+
+// Путь к конфигу по умолчанию, переопределяется через BOMBIE_CONFIG
+const DEFAULT_CONFIG_PATH: &str = "bombie.toml";
+
+// Общий таймаут автоматизации по умолчанию
+const DEFAULT_AUTOMATION_TIMEOUT_SECS: u64 = 30;
+
+// Повторы pip/playwright install при сетевых ошибках по умолчанию
+const DEFAULT_INSTALL_RETRIES: u32 = 3;
+
+// Таймаут одного шага очистки по умолчанию
+const DEFAULT_CLEANUP_TIMEOUT_SECS: u64 = 5;
+
+// Директории, удаляемые при delete_on_start по умолчанию
+const DEFAULT_LOG_DELETE_DIRS: [&str; 2] = ["logs", "recordings"];
+
+// Срок хранения ротированных логов по умолчанию
+const DEFAULT_LOG_MAX_AGE_DAYS: u64 = 7;
+
+// Предельный общий размер logs и recordings по умолчанию
+const DEFAULT_LOG_MAX_TOTAL_MB: u64 = 500;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ShutdownState {
@@ -8,27 +41,852 @@ pub enum ShutdownState {
     Completed = 2,
 }
 
+impl TryFrom<usize> for ShutdownState {
+    type Error = anyhow::Error;
+
+    fn try_from(value: usize) -> Result<Self> {
+        match value {
+            0 => Ok(Self::Running),
+            1 => Ok(Self::ShuttingDown),
+            2 => Ok(Self::Completed),
+            other => Err(anyhow!("Некорректное состояние остановки {}", other)),
+        }
+    }
+}
+
+/// Порядок шагов при graceful shutdown (SHUTDOWN_ORDER)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShutdownOrder {
+    /// Флаг остановки → очистка ресурсов → завершение дочерних процессов (по умолчанию)
+    #[default]
+    CleanupFirst,
+    /// Флаг остановки → завершение дочерних процессов → очистка ресурсов: браузеры
+    /// останавливаются до удаления кэша Playwright
+    SignalFirst,
+}
+
+impl ShutdownOrder {
+    /// Читает SHUTDOWN_ORDER (`cleanup-first` | `signal-first`), по умолчанию `cleanup-first`
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("SHUTDOWN_ORDER") {
+            Ok(value) => value.parse(),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+impl std::str::FromStr for ShutdownOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "cleanup-first" => Ok(Self::CleanupFirst),
+            "signal-first" => Ok(Self::SignalFirst),
+            other => Err(anyhow!(
+                "Некорректный SHUTDOWN_ORDER '{}' (допустимо: cleanup-first, signal-first)",
+                other
+            )),
+        }
+    }
+}
+
+/// Сборка мусора Python при очистке ресурсов (PYTHON_GC_ON_SHUTDOWN)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GcStrategy {
+    /// Без сборки, самый быстрый shutdown
+    None,
+    /// Один проход `gc.collect()` (по умолчанию)
+    #[default]
+    Once,
+    /// Несколько проходов до стабилизации и `gc.freeze()` - для больших сессий с циклическими ссылками
+    Aggressive,
+}
+
+impl GcStrategy {
+    /// Читает PYTHON_GC_ON_SHUTDOWN (`none` | `once` | `aggressive`), по умолчанию `once`
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("PYTHON_GC_ON_SHUTDOWN") {
+            Ok(value) => value.parse(),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Python код для `py.run`; `None`, если сборка не нужна
+    pub fn snippet(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Once => Some("import gc; gc.collect()"),
+            Self::Aggressive => Some(
+                r#"
+import gc
+for _ in range(5):
+    if gc.collect() == 0:
+        break
+gc.freeze()
+"#,
+            ),
+        }
+    }
+}
+
+impl std::str::FromStr for GcStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "" | "once" => Ok(Self::Once),
+            "aggressive" => Ok(Self::Aggressive),
+            other => Err(anyhow!(
+                "Некорректный PYTHON_GC_ON_SHUTDOWN '{}' (допустимо: none, once, aggressive)",
+                other
+            )),
+        }
+    }
+}
+
+/// Браузер, устанавливаемый через `playwright install`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaywrightBrowser {
+    #[default]
+    Chromium,
+    Firefox,
+    /// Ближе всего к Safari эмулируемых iOS устройств
+    Webkit,
+}
+
+impl PlaywrightBrowser {
+    /// Читает PLAYWRIGHT_BROWSERS (список через запятую, например `chromium,webkit`),
+    /// по умолчанию только chromium. Повторы убираются с сохранением порядка
+    pub fn from_env() -> Result<Vec<Self>> {
+        let value = match std::env::var("PLAYWRIGHT_BROWSERS") {
+            Ok(value) if !value.trim().is_empty() => value,
+            _ => return Ok(vec![Self::default()]),
+        };
+
+        let mut browsers = Vec::new();
+        for browser in value.split(',').filter(|entry| !entry.trim().is_empty()) {
+            let browser: Self = browser.parse()?;
+            if !browsers.contains(&browser) {
+                browsers.push(browser);
+            }
+        }
+        Ok(browsers)
+    }
+
+    /// Имя для `playwright install` и префикс директории в кэше (`webkit-1944`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Chromium => "chromium",
+            Self::Firefox => "firefox",
+            Self::Webkit => "webkit",
+        }
+    }
+}
+
+impl std::str::FromStr for PlaywrightBrowser {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "chromium" => Ok(Self::Chromium),
+            "firefox" => Ok(Self::Firefox),
+            "webkit" => Ok(Self::Webkit),
+            other => Err(anyhow!(
+                "Неизвестный браузер Playwright '{}' (допустимо: chromium, firefox, webkit)",
+                other
+            )),
+        }
+    }
+}
+
+/// Этапы запуска бота, идут строго по возрастанию
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Readiness {
+    Starting,
+    /// Python окружение и зависимости проверены
+    EnvironmentReady,
+    /// Эмулируемые устройства инициализированы
+    EmulationReady,
+    /// Автоматизация запущена
+    Running,
+}
+
 #[derive(Debug)]
 pub struct SystemConfig {
-    shutdown_signal: Arc<AtomicBool>,
+    shutdown_signal: watch::Sender<bool>,
     shutdown_state: Arc<AtomicUsize>,
+    readiness: watch::Sender<Readiness>,
+    automation_runs: AtomicU64,
+    automation_failures: AtomicU64,
+}
+
+/// Счетчики попыток автоматизации с момента запуска
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct AutomationCounters {
+    /// Все завершенные попытки, включая отмененные
+    pub runs: u64,
+    /// Попытки со статусом Failed или TimedOut
+    pub failures: u64,
+}
+
+impl Default for SystemConfig {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SystemConfig {
     pub fn new() -> Self {
         Self {
-            shutdown_signal: Arc::new(AtomicBool::new(false)),
+            shutdown_signal: watch::channel(false).0,
             shutdown_state: Arc::new(AtomicUsize::new(ShutdownState::Running as usize)),
+            readiness: watch::channel(Readiness::Starting).0,
+            automation_runs: AtomicU64::new(0),
+            automation_failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Отмечает достигнутый этап запуска и будит ожидающих `wait_ready`
+    pub fn set_readiness(&self, readiness: Readiness) {
+        self.readiness.send_replace(readiness);
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        *self.readiness.borrow()
+    }
+
+    /// Ожидает, пока бот не дойдет до `Readiness::Running` (окружение готово, эмуляция
+    /// инициализирована, автоматизация запущена). По истечении `timeout` - `ReadinessTimeout`
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<()> {
+        let mut receiver = self.readiness.subscribe();
+        let waited = tokio::time::timeout(timeout, async {
+            receiver.wait_for(|stage| *stage >= Readiness::Running).await.map(|_| ())
+        }).await;
+        match waited {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(anyhow!("Канал готовности закрыт: {}", e)),
+            Err(_) => Err(ReadinessTimeout {
+                timeout,
+                reached: self.readiness(),
+            }.into()),
         }
     }
 
-    pub fn request_shutdown(&self) -> anyhow::Result<()> {
-        self.shutdown_signal.store(true, Ordering::SeqCst);
+    pub fn request_shutdown(&self) {
+        self.shutdown_signal.send_replace(true);
         self.set_shutdown_state(ShutdownState::ShuttingDown);
-        Ok(())
+    }
+
+    /// Завершается, когда вызван `request_shutdown` (сразу, если уже вызван) - для фоновых задач,
+    /// которые должны остановиться вместе с процессом
+    pub async fn wait_shutdown(&self) {
+        let mut receiver = self.shutdown_signal.subscribe();
+        // Отправитель живет в self, поэтому канал не закрывается, пока идет ожидание
+        let _ = receiver.wait_for(|requested| *requested).await;
     }
 
     pub fn set_shutdown_state(&self, state: ShutdownState) {
         self.shutdown_state.store(state as usize, Ordering::SeqCst);
     }
-}
\ No newline at end of file
+
+    /// Текущее состояние остановки; записывается только через `ShutdownState`,
+    /// поэтому неизвестное значение недостижимо и считается `Completed`
+    pub fn shutdown_state(&self) -> ShutdownState {
+        ShutdownState::try_from(self.shutdown_state.load(Ordering::SeqCst))
+            .unwrap_or(ShutdownState::Completed)
+    }
+
+    /// Остановка начата или завершена - долгие циклы должны прекратить работу
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown_state() != ShutdownState::Running
+    }
+
+    /// Учитывает завершенную попытку автоматизации в счетчиках
+    pub fn record_automation_run(&self, failed: bool) {
+        self.automation_runs.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.automation_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn automation_counters(&self) -> AutomationCounters {
+        AutomationCounters {
+            runs: self.automation_runs.load(Ordering::Relaxed),
+            failures: self.automation_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Конфигурация приложения из bombie.toml
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// Секция [automation]: произвольные параметры автоматизации (аккаунты, лимиты, флаги).
+    /// Rust не интерпретирует её и передает в initialize_automation как dict
+    pub automation: Value,
+    /// Секция [success]: условие успеха по значению, которое вернула initialize_automation.
+    /// Без секции успехом считается любое завершение без исключения
+    pub success: Option<SuccessPredicate>,
+    /// Секция [logging]: вывод логов Rust и ротация файла
+    pub logging: LoggingConfig,
+    /// Секция [telegram]: именованные сессии Telegram аккаунтов
+    pub telegram: TelegramConfig,
+    /// Секция [locale_pool]: локали, распределяемые по устройствам при инициализации
+    pub locale_pool: LocalePoolConfig,
+    /// Секции [devices.<device_id>]: параметры запуска отдельных устройств
+    pub devices: HashMap<String, DeviceRunConfig>,
+    /// Секция [python]: окружение Python и установка зависимостей
+    pub python: PythonConfig,
+    /// Секция [emulation]: параметры встроенных устройств и парка
+    pub emulation: EmulationConfig,
+    /// Секция [run]: общие параметры запуска (устройства переопределяют их в [devices.<device_id>])
+    pub run: RunConfig,
+    /// Секция [cleanup]: очистка при остановке
+    pub cleanup: CleanupConfig,
+}
+
+// Поля секций [python], [emulation], [run] и [cleanup] необязательны: незаданное поле
+// берется из переменной окружения, а без нее - значение по умолчанию
+
+/// Читает и разбирает переменную окружения; пустое или некорректное значение - `None`
+fn env_value<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|value| value.trim().parse().ok())
+}
+
+/// Окружение Python и установка зависимостей
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PythonConfig {
+    /// Существующее окружение (PYTHON_VENV_PATH)
+    pub venv_path: Option<PathBuf>,
+    /// Системный интерпретатор вместо окружения (USE_SYSTEM_PYTHON)
+    pub use_system: Option<bool>,
+    /// pip install даже без изменений requirements (FORCE_REINSTALL)
+    pub force_reinstall: Option<bool>,
+    /// Повторы установки при сетевых ошибках (PIP_INSTALL_RETRIES)
+    pub install_retries: Option<u32>,
+    /// Браузеры Playwright для установки (PLAYWRIGHT_BROWSERS)
+    pub playwright_browsers: Option<Vec<PlaywrightBrowser>>,
+    /// Кэш скачанных браузеров Playwright. Переменная PLAYWRIGHT_BROWSERS_PATH важнее
+    pub playwright_browsers_path: Option<PathBuf>,
+}
+
+impl PythonConfig {
+    pub fn venv_path(&self) -> Option<PathBuf> {
+        self.venv_path.clone().or_else(|| {
+            std::env::var("PYTHON_VENV_PATH")
+                .ok()
+                .map(|path| path.trim().to_string())
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+
+    pub fn use_system(&self) -> bool {
+        self.use_system.unwrap_or_else(|| env_flag("USE_SYSTEM_PYTHON", false))
+    }
+
+    pub fn force_reinstall(&self) -> bool {
+        self.force_reinstall.unwrap_or_else(|| env_flag("FORCE_REINSTALL", false))
+    }
+
+    pub fn install_retries(&self) -> u32 {
+        self.install_retries
+            .or_else(|| env_value("PIP_INSTALL_RETRIES"))
+            .unwrap_or(DEFAULT_INSTALL_RETRIES)
+    }
+
+    /// Кэш браузеров Playwright: PLAYWRIGHT_BROWSERS_PATH, затем `playwright_browsers_path`.
+    /// В отличие от остальных полей переменная важнее файла - ее понимает и сам Playwright,
+    /// поэтому бот не должен скачивать браузеры не туда, где Playwright их будет искать
+    pub fn playwright_browsers_path(&self) -> Option<PathBuf> {
+        std::env::var("PLAYWRIGHT_BROWSERS_PATH")
+            .ok()
+            .map(|path| path.trim().to_string())
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| self.playwright_browsers_path.clone())
+    }
+
+    /// Браузеры без повторов с сохранением порядка; пустой список - только chromium
+    pub fn playwright_browsers(&self) -> Result<Vec<PlaywrightBrowser>> {
+        let Some(configured) = &self.playwright_browsers else {
+            return PlaywrightBrowser::from_env();
+        };
+        let mut browsers = Vec::new();
+        for browser in configured {
+            if !browsers.contains(browser) {
+                browsers.push(*browser);
+            }
+        }
+        if browsers.is_empty() {
+            browsers.push(PlaywrightBrowser::default());
+        }
+        Ok(browsers)
+    }
+}
+
+/// Параметры встроенных устройств и парка
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EmulationConfig {
+    /// Версия iOS встроенного устройства (EMULATED_IOS_VERSION)
+    pub ios_version: Option<String>,
+    /// Версия Android встроенного устройства (EMULATED_ANDROID_VERSION)
+    pub android_version: Option<String>,
+    /// Плотность экрана встроенного Android устройства (EMULATED_ANDROID_DENSITY)
+    pub android_density: Option<DensityBucket>,
+    /// Максимум устройств, 0 - без лимита (MAX_DEVICES)
+    pub max_devices: Option<usize>,
+    /// Файл профилей вместо встроенных устройств (DEVICE_PROFILES)
+    pub profiles: Option<PathBuf>,
+}
+
+impl EmulationConfig {
+    /// Версия iOS из конфига или окружения; `None` - версия по умолчанию
+    pub fn ios_version(&self) -> Option<String> {
+        self.ios_version.clone().or_else(|| std::env::var("EMULATED_IOS_VERSION").ok())
+    }
+
+    /// Версия Android из конфига или окружения; `None` - версия по умолчанию
+    pub fn android_version(&self) -> Option<String> {
+        self.android_version.clone().or_else(|| std::env::var("EMULATED_ANDROID_VERSION").ok())
+    }
+
+    pub fn android_density(&self) -> Result<Option<DensityBucket>> {
+        if self.android_density.is_some() {
+            return Ok(self.android_density);
+        }
+        match std::env::var("EMULATED_ANDROID_DENSITY") {
+            Ok(value) if !value.trim().is_empty() => Ok(Some(value.parse::<DensityBucket>()?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Лимит устройств; `None` - без лимита
+    pub fn max_devices(&self) -> Option<usize> {
+        self.max_devices
+            .or_else(|| env_value("MAX_DEVICES"))
+            .filter(|capacity| *capacity > 0)
+    }
+
+    pub fn profiles(&self) -> Option<PathBuf> {
+        self.profiles.clone().or_else(|| {
+            std::env::var("DEVICE_PROFILES")
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+}
+
+/// Общие параметры запуска автоматизации
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RunConfig {
+    /// Таймаут автоматизации в секундах, 0 - без таймаута (AUTOMATION_TIMEOUT_SECS)
+    pub automation_timeout_secs: Option<u64>,
+}
+
+impl RunConfig {
+    /// Общий таймаут автоматизации (по умолчанию 30 секунд); `None` - без таймаута
+    pub fn automation_timeout(&self) -> Option<Duration> {
+        let secs = self.automation_timeout_secs
+            .or_else(|| env_value("AUTOMATION_TIMEOUT_SECS"))
+            .unwrap_or(DEFAULT_AUTOMATION_TIMEOUT_SECS);
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+}
+
+/// Очистка при остановке
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CleanupConfig {
+    /// Таймаут каждого шага очистки в секундах (CLEANUP_TIMEOUT_SECS)
+    pub timeout_secs: Option<u64>,
+    /// Удалять скачанные браузеры Playwright (CLEANUP_PLAYWRIGHT_CACHE)
+    pub playwright_cache: Option<bool>,
+}
+
+impl CleanupConfig {
+    pub fn step_timeout(&self) -> Duration {
+        let secs = self.timeout_secs
+            .or_else(|| env_value("CLEANUP_TIMEOUT_SECS"))
+            .unwrap_or(DEFAULT_CLEANUP_TIMEOUT_SECS);
+        Duration::from_secs(secs)
+    }
+
+    pub fn playwright_cache(&self) -> bool {
+        self.playwright_cache.unwrap_or_else(|| env_flag("CLEANUP_PLAYWRIGHT_CACHE", false))
+    }
+}
+
+/// Параметры запуска устройства, переопределяющие общие
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DeviceRunConfig {
+    /// Таймаут автоматизации устройства в секундах
+    pub automation_timeout_secs: Option<u64>,
+    /// Режим DNS-over-HTTPS браузера устройства; без него - поведение браузера по умолчанию
+    pub doh: Option<DohConfig>,
+}
+
+/// Набор локалей (язык + часовой пояс) для парка устройств. Пустой набор - профили не меняются
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LocalePoolConfig {
+    pub strategy: LocaleStrategy,
+    pub locales: Vec<LocaleProfile>,
+}
+
+/// Именованные Telegram сессии. Активная выбирается через TELEGRAM_SESSION или `session`;
+/// без выбранной сессии Python берет учетные данные из TELEGRAM_API_ID/HASH/PHONE
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TelegramConfig {
+    pub session: Option<String>,
+    pub sessions: HashMap<String, TelegramSession>,
+}
+
+/// Учетные данные аккаунта. Строки поддерживают подстановку переменных окружения `${NAME}`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelegramSession {
+    pub api_id: String,
+    pub api_hash: String,
+    pub phone: String,
+    /// Файл сессии telethon, по умолчанию `.py_session/<имя сессии>.session`
+    pub session_file: Option<PathBuf>,
+}
+
+/// Выбранная сессия с подставленными переменными окружения, передается в Python
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedTelegramSession {
+    pub name: String,
+    pub api_id: i64,
+    pub api_hash: String,
+    pub phone: String,
+    pub session_file: PathBuf,
+}
+
+impl TelegramConfig {
+    /// Возвращает активную сессию (TELEGRAM_SESSION имеет приоритет над `session`).
+    /// Проверяет, что файл сессии существует или его директорию можно создать
+    pub fn selected(&self) -> Result<Option<ResolvedTelegramSession>> {
+        let name = match std::env::var("TELEGRAM_SESSION").ok().filter(|name| !name.trim().is_empty()) {
+            Some(name) => name.trim().to_string(),
+            None => match &self.session {
+                Some(name) => name.clone(),
+                None => return Ok(None),
+            },
+        };
+
+        let session = self.sessions.get(&name).ok_or_else(|| {
+            let mut available: Vec<&String> = self.sessions.keys().collect();
+            available.sort();
+            anyhow!("Telegram сессия '{}' не найдена в [telegram.sessions] (доступны: {:?})", name, available)
+        })?;
+
+        let api_id = interpolate_env(&session.api_id)?;
+        let api_id = api_id
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| anyhow!("Некорректный api_id сессии '{}': '{}'", name, api_id))?;
+
+        let session_file = match &session.session_file {
+            Some(path) => PathBuf::from(interpolate_env(&path.to_string_lossy())?),
+            None => Path::new(".py_session").join(format!("{}.session", name)),
+        };
+
+        if !session_file.exists() {
+            let parent = session_file.parent().filter(|parent| !parent.as_os_str().is_empty());
+            if let Some(parent) = parent {
+                fs::create_dir_all(parent).map_err(|e| anyhow!(
+                    "Невозможно создать директорию сессии {}: {}", parent.display(), e
+                ))?;
+            }
+            info!("Файл сессии {} не найден, будет создан при входе", session_file.display());
+        }
+
+        Ok(Some(ResolvedTelegramSession {
+            name,
+            api_id,
+            api_hash: interpolate_env(&session.api_hash)?,
+            phone: interpolate_env(&session.phone)?,
+            session_file,
+        }))
+    }
+}
+
+/// Подставляет значения переменных окружения вместо `${NAME}`; незаданная переменная - ошибка
+pub fn interpolate_env(value: &str) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("Незакрытая подстановка в '{}'", value))?;
+        let name = &rest[start + 2..start + end];
+        let substituted = std::env::var(name)
+            .map_err(|_| anyhow!("Переменная окружения {} не задана", name))?;
+        result.push_str(&substituted);
+        rest = &rest[start + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Настройки логирования: stderr и/или файл `<dir>/bombie-bot.log` с ротацией по размеру
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub dir: PathBuf,
+    /// Писать лог в файл с ротацией
+    pub file: bool,
+    /// Дублировать лог в stderr
+    pub stderr: bool,
+    /// Размер файла в мегабайтах, после которого выполняется ротация
+    pub max_size_mb: u64,
+    /// Сколько ротированных файлов хранить
+    pub max_files: usize,
+    /// Удалять директории delete_dirs при запуске (DELETE_LOG_FIRST_START)
+    pub delete_on_start: Option<bool>,
+    /// Директории для удаления, относительно директории проекта (LOG_DELETE_DIRS через запятую)
+    pub delete_dirs: Option<Vec<String>>,
+    /// Формат записей: text или json (LOG_FORMAT)
+    pub format: Option<LogFormat>,
+    /// Ротировать директории delete_dirs при запуске вместо удаления (LOG_ROTATE_ON_START)
+    pub rotate_on_start: Option<bool>,
+    /// Сколько дней хранить ротированные файлы (LOG_MAX_AGE_DAYS)
+    pub max_age_days: Option<u64>,
+    /// Предельный общий размер директорий delete_dirs в мегабайтах, 0 - без ограничения (LOG_MAX_TOTAL_MB)
+    pub max_total_mb: Option<u64>,
+}
+
+/// Формат записей лога
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Читаемый формат env_logger (по умолчанию)
+    #[default]
+    Text,
+    /// Одна JSON запись на строку: timestamp, level, module, message - для Loki и других агрегаторов
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            other => Err(anyhow!("Некорректный LOG_FORMAT '{}' (допустимо: text, json)", other)),
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn format(&self) -> Result<LogFormat> {
+        match self.format {
+            Some(format) => Ok(format),
+            None => match std::env::var("LOG_FORMAT") {
+                Ok(value) => value.parse(),
+                Err(_) => Ok(LogFormat::default()),
+            },
+        }
+    }
+
+    pub fn delete_on_start(&self) -> bool {
+        self.delete_on_start.unwrap_or_else(|| env_flag("DELETE_LOG_FIRST_START", false))
+    }
+
+    pub fn delete_dirs(&self) -> Vec<String> {
+        if let Some(dirs) = &self.delete_dirs {
+            return dirs.clone();
+        }
+        match std::env::var("LOG_DELETE_DIRS") {
+            Ok(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|dir| !dir.is_empty())
+                .map(String::from)
+                .collect(),
+            Err(_) => DEFAULT_LOG_DELETE_DIRS.iter().map(|dir| dir.to_string()).collect(),
+        }
+    }
+
+    pub fn rotate_on_start(&self) -> bool {
+        self.rotate_on_start.unwrap_or_else(|| env_flag("LOG_ROTATE_ON_START", false))
+    }
+
+    pub fn max_age_days(&self) -> u64 {
+        self.max_age_days
+            .or_else(|| env_value("LOG_MAX_AGE_DAYS"))
+            .unwrap_or(DEFAULT_LOG_MAX_AGE_DAYS)
+    }
+
+    pub fn max_total_bytes(&self) -> u64 {
+        self.max_total_mb
+            .or_else(|| env_value("LOG_MAX_TOTAL_MB"))
+            .unwrap_or(DEFAULT_LOG_MAX_TOTAL_MB)
+            .saturating_mul(1024 * 1024)
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("logs"),
+            file: false,
+            stderr: true,
+            max_size_mb: 10,
+            max_files: 5,
+            delete_on_start: None,
+            delete_dirs: None,
+            format: None,
+            rotate_on_start: None,
+            max_age_days: None,
+            max_total_mb: None,
+        }
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            automation: Value::Object(Map::new()),
+            success: None,
+            logging: LoggingConfig::default(),
+            telegram: TelegramConfig::default(),
+            locale_pool: LocalePoolConfig::default(),
+            devices: HashMap::new(),
+            python: PythonConfig::default(),
+            emulation: EmulationConfig::default(),
+            run: RunConfig::default(),
+            cleanup: CleanupConfig::default(),
+        }
+    }
+}
+
+/// Условие успеха автоматизации: значение по пути `path` (через точку, например "result.status")
+/// в возвращенном JSON должно быть равно `equals`
+#[derive(Debug, Clone, Deserialize)]
+pub struct SuccessPredicate {
+    pub path: String,
+    pub equals: Value,
+}
+
+impl SuccessPredicate {
+    pub fn check(&self, value: &Value) -> bool {
+        let pointer = if self.path.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", self.path.replace('.', "/"))
+        };
+        value.pointer(&pointer) == Some(&self.equals)
+    }
+}
+
+impl AppConfig {
+    /// Сколько вкладок/контекстов одного устройства автоматизация может вести параллельно
+    /// (`automation.tab_concurrency`, по умолчанию 1). Не путать с параллелизмом между устройствами
+    pub fn tab_concurrency(&self) -> Result<usize> {
+        match self.automation.get("tab_concurrency") {
+            None => Ok(1),
+            Some(value) => value
+                .as_u64()
+                .filter(|value| *value >= 1)
+                .map(|value| value as usize)
+                .ok_or_else(|| anyhow!("automation.tab_concurrency должно быть целым числом >= 1, получено {}", value)),
+        }
+    }
+
+    /// Загружает конфиг из BOMBIE_CONFIG или bombie.toml; при отсутствии файла возвращает значения
+    /// по умолчанию. Вызывается до инициализации логгера, поэтому выбранный источник возвращается
+    /// вызывающему для записи в лог
+    pub fn load() -> Result<(Self, ConfigSource)> {
+        let path = PathBuf::from(std::env::var("BOMBIE_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string()));
+        if !path.exists() {
+            return Ok((Self::default(), ConfigSource::Defaults(path)));
+        }
+        let config = Self::from_path(&path)?;
+        Ok((config, ConfigSource::File(path)))
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Не удалось прочитать {}: {}", path.display(), e))?;
+        let config: Self = toml::from_str(&content)
+            .map_err(|e| anyhow!("Ошибка разбора {}: {}", path.display(), e))?;
+
+        if !config.automation.is_object() {
+            return Err(anyhow!("Секция [automation] в {} должна быть таблицей", path.display()));
+        }
+        config.tab_concurrency()?;
+        Ok(config)
+    }
+}
+
+/// Откуда взята конфигурация `AppConfig::load`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// Файл конфигурации
+    File(PathBuf),
+    /// Файл не найден, используются значения по умолчанию
+    Defaults(PathBuf),
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "файл {}", path.display()),
+            Self::Defaults(path) => write!(f, "значения по умолчанию (файл {} не найден)", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn wait_ready_returns_once_running() {
+        let config = Arc::new(SystemConfig::new());
+        let starter = Arc::clone(&config);
+        tokio::spawn(async move {
+            for stage in [Readiness::EnvironmentReady, Readiness::EmulationReady, Readiness::Running] {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                starter.set_readiness(stage);
+            }
+        });
+
+        config.wait_ready(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(config.readiness(), Readiness::Running);
+    }
+
+    #[tokio::test]
+    async fn wait_ready_times_out_with_reached_stage() {
+        let config = SystemConfig::new();
+        config.set_readiness(Readiness::EmulationReady);
+
+        let error = config.wait_ready(Duration::from_millis(20)).await.unwrap_err();
+        let timeout = error.downcast_ref::<ReadinessTimeout>().expect("ожидалась ошибка ReadinessTimeout");
+        assert_eq!(timeout.reached, Readiness::EmulationReady);
+        assert_eq!(timeout.timeout, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn device_doh_parses_from_toml() {
+        let off: DeviceRunConfig = toml::from_str(r#"doh = "Off""#).unwrap();
+        assert_eq!(off.doh, Some(DohConfig::Off));
+
+        let secure: DeviceRunConfig =
+            toml::from_str(r#"doh = { Secure = { template = "https://dns.example/dns-query" } }"#).unwrap();
+        assert_eq!(secure.doh, Some(DohConfig::Secure { template: "https://dns.example/dns-query".to_string() }));
+    }
+}
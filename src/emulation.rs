@@ -1,15 +1,40 @@
 use anyhow::{Result, anyhow};
 use std::sync::Arc;
-use log::info;
-use tokio::sync::RwLock;
-use chromiumoxide::BrowserConfig;
+use std::sync::atomic::{AtomicU64, Ordering};
+use log::{info, warn};
+use tokio::sync::{mpsc, RwLock};
+use chromiumoxide::{BrowserConfig, Page};
+use chromiumoxide::cdp::browser_protocol::network::{ConnectionType, EmulateNetworkConditionsParams};
+use chromiumoxide::handler::viewport::Viewport;
 use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use once_cell::sync::OnceCell;
+use chrono_tz::TZ_VARIANTS;
+use crate::proxy::{ProxyConfig, ProxyPool};
+use crate::config::{EmulationConfig, LocalePoolConfig};
+use crate::utils::env_flag;
+use rand::seq::SliceRandom;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 // Глобальное состояние эмулируемых устройств
 static GLOBAL_DEVICES: OnceCell<Arc<RwLock<DeviceManager>>> = OnceCell::new();
 
+// Идентификаторы устройств парка по умолчанию
+pub const DEFAULT_IOS_DEVICE_ID: &str = "ios_device";
+pub const DEFAULT_ANDROID_DEVICE_ID: &str = "android_device";
+
+// Версия формата снимка состояния эмуляции, увеличивается при несовместимых изменениях
+const SNAPSHOT_SCHEMA_VERSION: u32 = 1;
+
+// Версии ОС по умолчанию для эмулируемых устройств
+const DEFAULT_IOS_VERSION: &str = "16.0";
+const DEFAULT_ANDROID_VERSION: &str = "13";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceMetadata {
     pub device_id: String,
@@ -23,24 +48,376 @@ pub struct DeviceMetadata {
     pub webview_data: WebViewData,
     pub hardware_info: HardwareInfo,
     pub connection_info: ConnectionInfo,
+    /// Состояния Permissions API по имени разрешения ("geolocation", "notifications", ...)
+    #[serde(default)]
+    pub permissions: HashMap<String, PermissionState>,
+    /// Эмулируемая геопозиция; если задана, geolocation по умолчанию считается granted
+    #[serde(default)]
+    pub geolocation: Option<Geolocation>,
+    /// Дополнительные HTTP заголовки устройства (CDP `Network.setExtraHTTPHeaders`)
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+    /// Client hints, явно заданные через `set_user_agent`; без них выводятся из user agent
+    #[serde(default)]
+    pub client_hints: Option<ClientHints>,
+    /// Режим контекста браузера: одноразовый (incognito) или с профилем на диске
+    #[serde(default)]
+    pub context_mode: ContextMode,
+    /// Директория профиля браузера, обязательна для `ContextMode::Persistent`
+    #[serde(default)]
+    pub user_data_dir: Option<PathBuf>,
+}
+
+/// User-Agent Client Hints (`Sec-CH-UA-*`, `navigator.userAgentData`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClientHints {
+    pub platform: String,
+    pub platform_version: String,
+    pub model: String,
+    pub mobile: bool,
+    /// Бренды браузера (бренд, major версия); пусто для Safari, который client hints не отправляет
+    pub brands: Vec<(String, String)>,
+}
+
+impl ClientHints {
+    /// Выводит client hints из строки user agent: ОС, версию, модель (Android) и версию Chrome
+    pub fn from_user_agent(user_agent: &str) -> Result<Self> {
+        let mobile = user_agent.contains("Mobile");
+
+        let (platform, platform_version, model) = if let Some(rest) = after(user_agent, "iPhone OS ") {
+            let version = rest.split_whitespace().next().unwrap_or_default().replace('_', ".");
+            ("iOS".to_string(), version, String::new())
+        } else if let Some(rest) = after(user_agent, "Android ") {
+            let mut parts = rest.split(')').next().unwrap_or_default().split(';').map(str::trim);
+            let version = parts.next().unwrap_or_default().to_string();
+            let model = parts.next().unwrap_or_default().to_string();
+            ("Android".to_string(), version, model)
+        } else {
+            return Err(anyhow!("Не удалось определить ОС из user agent: {}", user_agent));
+        };
+        parse_os_version(&platform_version)?;
+
+        let brands = match after(user_agent, "Chrome/") {
+            Some(rest) => {
+                let major = rest.split('.').next().unwrap_or_default().to_string();
+                vec![
+                    ("Chromium".to_string(), major.clone()),
+                    ("Google Chrome".to_string(), major),
+                    ("Not A(Brand".to_string(), "99".to_string()),
+                ]
+            }
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            platform,
+            platform_version,
+            model,
+            mobile,
+            brands,
+        })
+    }
+
+    /// Проверяет, что заданные hints не противоречат выведенным из user agent
+    fn validate_against(&self, derived: &ClientHints) -> Result<()> {
+        let major = |version: &str| version.split('.').next().unwrap_or_default().to_string();
+        let mut mismatches = Vec::new();
+
+        if self.platform != derived.platform {
+            mismatches.push(format!("platform '{}' != '{}'", self.platform, derived.platform));
+        }
+        if major(&self.platform_version) != major(&derived.platform_version) {
+            mismatches.push(format!("platform_version '{}' != '{}'", self.platform_version, derived.platform_version));
+        }
+        if !derived.model.is_empty() && self.model != derived.model {
+            mismatches.push(format!("model '{}' != '{}'", self.model, derived.model));
+        }
+        if self.mobile != derived.mobile {
+            mismatches.push(format!("mobile {} != {}", self.mobile, derived.mobile));
+        }
+
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Client hints не соответствуют user agent: {}", mismatches.join(", ")))
+        }
+    }
+}
+
+fn after<'a>(haystack: &'a str, needle: &str) -> Option<&'a str> {
+    haystack.find(needle).map(|index| &haystack[index + needle.len()..])
+}
+
+// Заголовки, которые Chromium выставляет сам; переопределение ломает запросы или игнорируется
+const MANAGED_HEADERS: &[&str] = &[
+    "host",
+    "content-length",
+    "connection",
+    "transfer-encoding",
+    "upgrade",
+    "cookie",
+    "te",
+    "keep-alive",
+    "proxy-connection",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermissionState {
+    Granted,
+    Denied,
+    Prompt,
+}
+
+/// Как Python сторона создает контекст: `new_context` поверх чистого браузера или
+/// `launch_persistent_context` с сохранением cookies/localStorage между запусками
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextMode {
+    #[default]
+    Incognito,
+    Persistent,
 }
 
+impl ContextMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContextMode::Incognito => "incognito",
+            ContextMode::Persistent => "persistent",
+        }
+    }
+}
+
+impl std::str::FromStr for ContextMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "incognito" => Ok(ContextMode::Incognito),
+            "persistent" => Ok(ContextMode::Persistent),
+            other => Err(anyhow!("Неизвестный режим контекста: '{}' (ожидается incognito или persistent)", other)),
+        }
+    }
+}
+
+/// Локаль устройства: язык (BCP 47, например "de-DE") и соответствующий ему часовой пояс IANA
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocaleProfile {
+    pub language: String,
+    pub timezone: String,
+}
+
+impl LocaleProfile {
+    /// Код языка без региона ("de" для "de-DE")
+    pub fn lang_code(&self) -> String {
+        self.language
+            .split(['-', '_'])
+            .next()
+            .unwrap_or(&self.language)
+            .to_lowercase()
+    }
+}
+
+/// Порядок выдачи локалей из пула
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocaleStrategy {
+    /// По кругу в порядке device_id
+    #[default]
+    RoundRobin,
+    /// Случайная локаль для каждого устройства
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Geolocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default)]
+    pub accuracy: f64,
+}
+
+/// Метрики дисплея. `width`/`height` — физический экран устройства (`screen.*` в JS),
+/// `viewport` — внутренняя область страницы (`window.inner*`, по ней считаются media queries),
+/// `window` — окно браузера вместе с интерфейсом
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScreenMetrics {
     pub width: u32,
     pub height: u32,
     pub pixel_ratio: f32,
     pub touch_points: u8,
+    pub viewport: Size,
+    pub window: Size,
+}
+
+impl ScreenMetrics {
+    /// Отклоняет вырожденные метрики: нулевые размеры экрана, viewport или окна,
+    /// неположительный pixel_ratio и viewport больше экрана
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(anyhow!("Нулевой размер экрана: {}x{}", self.width, self.height));
+        }
+        if self.viewport.width == 0 || self.viewport.height == 0 {
+            return Err(anyhow!("Нулевой размер viewport: {}x{}", self.viewport.width, self.viewport.height));
+        }
+        if self.window.width == 0 || self.window.height == 0 {
+            return Err(anyhow!("Нулевой размер окна: {}x{}", self.window.width, self.window.height));
+        }
+        if !(self.pixel_ratio.is_finite() && self.pixel_ratio > 0.0) {
+            return Err(anyhow!("Некорректный pixel_ratio: {}", self.pixel_ratio));
+        }
+        if self.viewport.width > self.width || self.viewport.height > self.height {
+            return Err(anyhow!(
+                "Viewport {}x{} больше экрана {}x{}",
+                self.viewport.width,
+                self.viewport.height,
+                self.width,
+                self.height
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Android density bucket: согласованные `devicePixelRatio` и размер экрана в CSS пикселях
+/// для типичной панели этой плотности
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DensityBucket {
+    /// ~160 dpi, 320x480 px
+    Mdpi,
+    /// ~240 dpi, 480x800 px
+    Hdpi,
+    /// ~320 dpi, 720x1280 px
+    Xhdpi,
+    /// ~480 dpi, 1080x1920 px
+    Xxhdpi,
+    /// ~640 dpi, 1440x3040 px
+    Xxxhdpi,
+}
+
+impl DensityBucket {
+    pub fn pixel_ratio(&self) -> f32 {
+        match self {
+            DensityBucket::Mdpi => 1.0,
+            DensityBucket::Hdpi => 1.5,
+            DensityBucket::Xhdpi => 2.0,
+            DensityBucket::Xxhdpi => 3.0,
+            DensityBucket::Xxxhdpi => 4.0,
+        }
+    }
+
+    /// Размер экрана в CSS пикселях (физическое разрешение / pixel_ratio)
+    pub fn screen_size(&self) -> Size {
+        match self {
+            DensityBucket::Mdpi => Size { width: 320, height: 480 },
+            DensityBucket::Hdpi => Size { width: 320, height: 533 },
+            DensityBucket::Xhdpi => Size { width: 360, height: 640 },
+            DensityBucket::Xxhdpi => Size { width: 360, height: 640 },
+            DensityBucket::Xxxhdpi => Size { width: 360, height: 760 },
+        }
+    }
+
+    /// Метрики экрана Android устройства этой плотности
+    pub fn screen_metrics(&self) -> ScreenMetrics {
+        let screen = self.screen_size();
+        ScreenMetrics {
+            width: screen.width,
+            height: screen.height,
+            pixel_ratio: self.pixel_ratio(),
+            touch_points: 5,
+            viewport: Size {
+                width: screen.width,
+                height: screen.height.saturating_sub(ANDROID_BROWSER_CHROME_HEIGHT),
+            },
+            window: screen,
+        }
+    }
+}
+
+impl std::str::FromStr for DensityBucket {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "mdpi" => Ok(DensityBucket::Mdpi),
+            "hdpi" => Ok(DensityBucket::Hdpi),
+            "xhdpi" => Ok(DensityBucket::Xhdpi),
+            "xxhdpi" => Ok(DensityBucket::Xxhdpi),
+            "xxxhdpi" => Ok(DensityBucket::Xxxhdpi),
+            other => Err(anyhow!(
+                "Неизвестный density bucket '{}' (допустимо: mdpi, hdpi, xhdpi, xxhdpi, xxxhdpi)",
+                other
+            )),
+        }
+    }
+}
+
+// Высота статус-бара, адресной строки Chrome и системной навигации Android в CSS пикселях
+const ANDROID_BROWSER_CHROME_HEIGHT: u32 = 132;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebViewData {
     pub engine_version: String,
-    pub supported_apis: Vec<String>,
+    pub supported_apis: Vec<WebApi>,
     webkit_flags: Option<WebKitFlags>,
     chrome_flags: Option<ChromeFlags>,
 }
 
+/// Web API, поддерживаемое эмулируемым WebView. В JSON хранится строкой ("WebGL"),
+/// неизвестные значения сохраняются в `Other` без потерь
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum WebApi {
+    WebKit,
+    WebView,
+    WebGL,
+    WebRTC,
+    WebAssembly,
+    ServiceWorker,
+    Other(String),
+}
+
+impl From<String> for WebApi {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "WebKit" => WebApi::WebKit,
+            "WebView" => WebApi::WebView,
+            "WebGL" => WebApi::WebGL,
+            "WebRTC" => WebApi::WebRTC,
+            "WebAssembly" => WebApi::WebAssembly,
+            "ServiceWorker" => WebApi::ServiceWorker,
+            _ => WebApi::Other(value),
+        }
+    }
+}
+
+impl From<WebApi> for String {
+    fn from(api: WebApi) -> Self {
+        match api {
+            WebApi::WebKit => "WebKit".to_string(),
+            WebApi::WebView => "WebView".to_string(),
+            WebApi::WebGL => "WebGL".to_string(),
+            WebApi::WebRTC => "WebRTC".to_string(),
+            WebApi::WebAssembly => "WebAssembly".to_string(),
+            WebApi::ServiceWorker => "ServiceWorker".to_string(),
+            WebApi::Other(value) => value,
+        }
+    }
+}
+
+impl WebViewData {
+    pub fn supports(&self, api: &WebApi) -> bool {
+        self.supported_apis.contains(api)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HardwareInfo {
     pub model: String,
@@ -56,6 +433,12 @@ pub struct ConnectionInfo {
     pub bandwidth: String,
     pub rtt: u32,
     pub throughput: u32,
+    /// Прокси устройства, назначается из пула через `DeviceManager::assign_proxies`
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    /// Режим экономии трафика: заголовок `Save-Data: on` и `navigator.connection.saveData`
+    #[serde(default)]
+    pub save_data: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -67,37 +450,236 @@ pub enum PlatformType {
 #[derive(Debug)]
 pub struct DeviceManager {
     pub devices: HashMap<String, EmulatedDevice>,
+    events: Option<mpsc::Sender<DeviceEvent>>,
+    /// Максимум устройств; при превышении вытесняется давно не использованное (LRU)
+    capacity: Option<usize>,
+    /// Логическое время последнего обращения к устройству. Геттеры берут `&self`,
+    /// поэтому порядок доступа хранится под собственным Mutex
+    last_access: std::sync::Mutex<HashMap<String, u64>>,
+    access_clock: AtomicU64,
 }
 
-#[derive(Debug, Clone)]
+/// Событие жизненного цикла устройства для внешнего мониторинга
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", content = "device_id")]
+pub enum DeviceEvent {
+    Created(String),
+    Updated(String),
+    Removed(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmulatedDevice {
     pub metadata: DeviceMetadata,
     pub browser: EmulatedBrowser,
+    /// Таймаут автоматизации этого устройства; без него действует общий таймаут
+    #[serde(default)]
+    pub automation_timeout: Option<Duration>,
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum EmulatedBrowser {
     Webkit(WebKitConfig),
     ChromiumBased(ChromiumConfig),
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebKitConfig {
     pub user_agent: String,
     pub webkit_version: String,
     pub platform_version: String,
     pub build_number: String,
+    pub doh: DohConfig,
+    /// Собственный бинарник Chromium вместо скачанного Playwright
+    pub browser_executable: Option<PathBuf>,
+    #[serde(default)]
+    pub protocol: ProtocolConfig,
+    #[serde(default)]
+    pub fingerprint: FingerprintConfig,
+    /// Дополнительные флаги запуска (см. `EmulatedBrowser::with_extra_args`)
+    #[serde(default)]
+    pub extra_args: Vec<String>,
 }
 
 #[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChromiumConfig {
     pub user_agent: String,
     pub chrome_version: String,
     pub webview_version: String,
     pub build_version: String,
+    // Нестабильный флаг --force-webview, на части сборок Chromium ломает запуск
+    pub force_webview: bool,
+    pub doh: DohConfig,
+    /// Собственный бинарник Chromium вместо скачанного Playwright
+    pub browser_executable: Option<PathBuf>,
+    #[serde(default)]
+    pub protocol: ProtocolConfig,
+    #[serde(default)]
+    pub fingerprint: FingerprintConfig,
+    /// Дополнительные флаги запуска (см. `EmulatedBrowser::with_extra_args`)
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+}
+
+/// Режим DNS-over-HTTPS эмулируемого браузера
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum DohConfig {
+    /// Стандартное поведение браузера, флаги не передаются
+    #[default]
+    BrowserDefault,
+    /// DoH выключен, используется системный резолвер
+    Off,
+    /// DoH при поддержке текущим DNS провайдером, с откатом на обычный DNS
+    Automatic,
+    /// Только указанный DoH провайдер (URL шаблон), без отката
+    Secure { template: String },
+}
+
+impl DohConfig {
+    /// Флаги Chromium для выбранного режима (через DnsOverHttps feature и field trial параметры)
+    pub fn chromium_args(&self) -> Vec<String> {
+        match self {
+            DohConfig::BrowserDefault => Vec::new(),
+            DohConfig::Off => vec!["--disable-features=DnsOverHttps".to_string()],
+            DohConfig::Automatic => vec!["--enable-features=DnsOverHttps".to_string()],
+            DohConfig::Secure { template } => vec![
+                "--enable-features=DnsOverHttps<DoHTrial".to_string(),
+                "--force-fieldtrials=DoHTrial/Group1".to_string(),
+                format!(
+                    "--force-fieldtrial-params=DoHTrial.Group1:Fallback/false/Templates/{}",
+                    escape_fieldtrial_value(template)
+                ),
+            ],
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let DohConfig::Secure { template } = self {
+            if !template.starts_with("https://") {
+                return Err(anyhow!("DoH шаблон должен начинаться с https://: '{}'", template));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Версия HTTP, которую браузер может согласовать с сервером
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpVersion {
+    /// Как в браузере по умолчанию: HTTP/3 (QUIC), HTTP/2 или HTTP/1.1 по ALPN
+    #[default]
+    Auto,
+    /// Только HTTP/1.1 (`--disable-http2`, без QUIC)
+    Http1,
+    /// Не выше HTTP/2: QUIC выключен, h2 выбирается по ALPN
+    Http2,
+}
+
+impl std::str::FromStr for HttpVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "auto" => Ok(HttpVersion::Auto),
+            "http1" | "http/1.1" => Ok(HttpVersion::Http1),
+            "http2" | "http/2" => Ok(HttpVersion::Http2),
+            other => Err(anyhow!("Некорректная версия HTTP '{}' (допустимо: auto, http1, http2)", other)),
+        }
+    }
+}
+
+/// Транспортные настройки браузера: QUIC и версия HTTP. Настройки HTTP/2 SETTINGS фрейма
+/// Chromium флагами не меняются, доступно только включение/выключение протоколов
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolConfig {
+    pub disable_quic: bool,
+    pub http_version: HttpVersion,
+}
+
+impl ProtocolConfig {
+    /// Настройки из BROWSER_DISABLE_QUIC и BROWSER_HTTP_VERSION
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            disable_quic: env_flag("BROWSER_DISABLE_QUIC", false),
+            http_version: match std::env::var("BROWSER_HTTP_VERSION") {
+                Ok(value) => value.parse()?,
+                Err(_) => HttpVersion::default(),
+            },
+        })
+    }
+
+    pub fn chromium_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        // HTTP/3 работает поверх QUIC, поэтому ограничение версии HTTP тоже выключает QUIC
+        if self.disable_quic || self.http_version != HttpVersion::Auto {
+            args.push("--disable-quic".to_string());
+        }
+        if self.http_version == HttpVersion::Http1 {
+            args.push("--disable-http2".to_string());
+        }
+        args
+    }
+}
+
+/// Подмена fingerprint графики. WebGL vendor/renderer и шум canvas подменяются init scripts
+/// устройства (`EmulatedDevice::init_scripts`): у Chromium нет флагов для этих строк
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FingerprintConfig {
+    /// WebGL через ANGLE (`--use-gl=angle`) и `UNMASKED_VENDOR/RENDERER_WEBGL` из `HardwareInfo`
+    pub spoof_webgl: bool,
+    /// Детерминированный для устройства шум в младших битах пикселей при чтении canvas
+    pub canvas_noise: bool,
+}
+
+impl Default for FingerprintConfig {
+    fn default() -> Self {
+        Self {
+            spoof_webgl: true,
+            canvas_noise: false,
+        }
+    }
+}
+
+impl FingerprintConfig {
+    /// Настройки из BROWSER_SPOOF_WEBGL (по умолчанию включено) и BROWSER_CANVAS_NOISE
+    pub fn from_env() -> Self {
+        Self {
+            spoof_webgl: env_flag("BROWSER_SPOOF_WEBGL", true),
+            canvas_noise: env_flag("BROWSER_CANVAS_NOISE", false),
+        }
+    }
+
+    pub fn chromium_args(&self) -> Vec<String> {
+        if self.spoof_webgl {
+            vec!["--use-gl=angle".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Экранирует значение параметра field trial (`/`, `:`, `.` и прочие спецсимволы -> %XX)
+fn escape_fieldtrial_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                (byte as char).to_string()
+            } else {
+                format!("%{:02X}", byte)
+            }
+        })
+        .collect()
+}
+
+impl Default for DeviceManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Реализация менеджера устройств
@@ -105,60 +687,420 @@ impl DeviceManager {
     pub fn new() -> Self {
         Self {
             devices: HashMap::new(),
+            events: None,
+            capacity: None,
+            last_access: std::sync::Mutex::new(HashMap::new()),
+            access_clock: AtomicU64::new(0),
         }
     }
 
-    pub async fn create_ios_device(&mut self, device_id: &str) -> Result<()> {
-        let metadata = self.generate_ios_metadata(device_id)?;
-        
-        let webkit_config = WebKitConfig {
-            user_agent: metadata.user_agent.clone(),
-            webkit_version: "605.1.15".to_string(),
-            platform_version: metadata.hardware_info.platform_version.clone(),
-            build_number: "15E148".to_string(),
+    /// Менеджер не более чем на `capacity` устройств: при создании нового устройства сверх
+    /// лимита удаляется то, к которому дольше всего не обращались
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity: Some(capacity.max(1)),
+            ..Self::new()
+        }
+    }
+
+    /// Отмечает обращение к устройству для LRU
+    fn touch(&self, device_id: &str) {
+        let tick = self.access_clock.fetch_add(1, Ordering::Relaxed);
+        self.last_access
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(device_id.to_string(), tick);
+    }
+
+    /// Добавляет устройство, при необходимости освобождая место вытеснением LRU устройств
+    fn insert_device(&mut self, device_id: &str, device: EmulatedDevice) {
+        if let Some(capacity) = self.capacity {
+            while !self.devices.contains_key(device_id) && self.devices.len() >= capacity {
+                if self.evict_lru().is_none() {
+                    break;
+                }
+            }
+        }
+        self.devices.insert(device_id.to_string(), device);
+        self.touch(device_id);
+        self.emit(DeviceEvent::Created(device_id.to_string()));
+    }
+
+    /// Удаляет устройство, к которому дольше всего не обращались. Удаление идет через
+    /// `remove_device`, так что подписчики событий получают `Removed` и освобождают свои ресурсы
+    fn evict_lru(&mut self) -> Option<String> {
+        let device_id = {
+            let last_access = self.last_access.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            self.devices
+                .keys()
+                .min_by_key(|device_id| last_access.get(*device_id).copied().unwrap_or(0))
+                .cloned()?
         };
-        
-        self.devices.insert(
-            device_id.to_string(),
-            EmulatedDevice {
-                metadata: metadata.clone(),
-                browser: EmulatedBrowser::Webkit(webkit_config),
-            },
+        self.remove_device(&device_id).ok()?;
+        info!(
+            "Устройство {} вытеснено как давно не использованное (лимит {} устройств)",
+            device_id,
+            self.capacity.unwrap_or_default()
         );
+        Some(device_id)
+    }
+
+    /// Заменяет все устройства (восстановление снимка); порядок LRU начинается заново
+    pub fn replace_devices(&mut self, devices: HashMap<String, EmulatedDevice>) {
+        self.devices = devices;
+        self.last_access.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
+        for device_id in self.device_ids() {
+            self.touch(&device_id);
+        }
+        if let Some(capacity) = self.capacity {
+            while self.devices.len() > capacity && self.evict_lru().is_some() {}
+        }
+    }
+
+    /// Подключает канал событий устройств. Отправка не блокирует: при переполненном
+    /// или закрытом канале событие отбрасывается с предупреждением
+    pub fn set_event_sender(&mut self, sender: mpsc::Sender<DeviceEvent>) {
+        self.events = Some(sender);
+    }
+
+    fn emit(&self, event: DeviceEvent) {
+        if let Some(sender) = &self.events {
+            if let Err(e) = sender.try_send(event) {
+                warn!("Событие устройства не доставлено: {}", e);
+            }
+        }
+    }
+
+    /// Заменяет user agent устройства (см. `EmulatedDevice::set_user_agent`)
+    pub fn set_user_agent(&mut self, device_id: &str, user_agent: &str, hints: Option<ClientHints>) -> Result<()> {
+        let device = self.devices.get_mut(device_id)
+            .ok_or_else(|| anyhow!("Device not found"))?;
+        device.set_user_agent(user_agent, hints)?;
+        self.touch(device_id);
+        self.emit(DeviceEvent::Updated(device_id.to_string()));
         Ok(())
     }
 
-    pub async fn create_android_device(&mut self, device_id: &str) -> Result<()> {
-        let metadata = self.generate_android_metadata(device_id)?;
-        
-        let chrome_config = ChromiumConfig {
-            user_agent: metadata.user_agent.clone(),
-            chrome_version: "97.0.4692.98".to_string(),
-            webview_version: metadata.webview_data.engine_version.clone(),
-            build_version: "4692.98".to_string(),
-        };
-        
-        self.devices.insert(
-            device_id.to_string(),
+    /// Задает режим DNS-over-HTTPS браузера устройства (`[devices.<device_id>] doh`)
+    pub fn set_doh(&mut self, device_id: &str, doh: DohConfig) -> Result<()> {
+        let device = self.devices.get_mut(device_id)
+            .ok_or_else(|| anyhow!("Device not found"))?;
+        device.browser = device.browser.with_doh(doh)?;
+        self.touch(device_id);
+        self.emit(DeviceEvent::Updated(device_id.to_string()));
+        Ok(())
+    }
+
+    /// Переопределяет таймаут автоматизации устройства; `None` возвращает общий таймаут
+    pub fn set_automation_timeout(&mut self, device_id: &str, automation_timeout: Option<Duration>) -> Result<()> {
+        let device = self.devices.get_mut(device_id)
+            .ok_or_else(|| anyhow!("Device not found"))?;
+        device.automation_timeout = automation_timeout;
+        self.touch(device_id);
+        self.emit(DeviceEvent::Updated(device_id.to_string()));
+        Ok(())
+    }
+
+    /// Заменяет метаданные устройства (ротация личности без перезапуска). Метаданные проверяются
+    /// так же, как при создании; настройки браузера (DoH, протоколы, флаги) сохраняются, кроме
+    /// user agent. При смене платформы браузер создается заново
+    pub fn update_metadata(&mut self, device_id: &str, mut metadata: DeviceMetadata) -> Result<()> {
+        if metadata.device_id != device_id {
+            return Err(anyhow!(
+                "device_id метаданных '{}' не совпадает с обновляемым устройством '{}'",
+                metadata.device_id,
+                device_id
+            ));
+        }
+        let device = self.devices.get_mut(device_id)
+            .ok_or_else(|| anyhow!("Device not found"))?;
+        metadata.prepare()?;
+
+        match (&mut device.browser, &metadata.platform) {
+            (EmulatedBrowser::Webkit(webkit_config), PlatformType::IOS) => {
+                webkit_config.user_agent = metadata.user_agent.clone();
+                webkit_config.platform_version = metadata.hardware_info.platform_version.clone();
+            }
+            (EmulatedBrowser::ChromiumBased(chrome_config), PlatformType::Android) => {
+                chrome_config.user_agent = metadata.user_agent.clone();
+            }
+            _ => device.browser = EmulatedBrowser::for_metadata(&metadata)?,
+        }
+        device.metadata = metadata;
+        self.touch(device_id);
+        self.emit(DeviceEvent::Updated(device_id.to_string()));
+        Ok(())
+    }
+
+    /// Удаляет устройство из парка
+    pub fn remove_device(&mut self, device_id: &str) -> Result<EmulatedDevice> {
+        let device = self.devices.remove(device_id)
+            .ok_or_else(|| anyhow!("Device not found"))?;
+        self.last_access.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(device_id);
+        self.emit(DeviceEvent::Removed(device_id.to_string()));
+        Ok(device)
+    }
+
+    pub async fn create_ios_device(&mut self, device_id: &str) -> Result<()> {
+        self.create_ios_device_with_version(device_id, DEFAULT_IOS_VERSION).await
+    }
+
+    /// Создает iOS устройство с указанной версией ОС (например "17.2")
+    pub async fn create_ios_device_with_version(&mut self, device_id: &str, os_version: &str) -> Result<()> {
+        let metadata = Self::generate_ios_metadata(device_id, os_version)?;
+        self.register_device(metadata)
+    }
+
+    /// Создает Android устройство; `density` заменяет метрики экрана Galaxy S21 Ultra
+    /// согласованными метриками выбранного density bucket
+    pub async fn create_android_device(&mut self, device_id: &str, density: Option<DensityBucket>) -> Result<()> {
+        self.create_android_device_with_version(device_id, DEFAULT_ANDROID_VERSION, density).await
+    }
+
+    /// Создает Android устройство с указанной версией ОС (например "14")
+    pub async fn create_android_device_with_version(
+        &mut self,
+        device_id: &str,
+        os_version: &str,
+        density: Option<DensityBucket>,
+    ) -> Result<()> {
+        let metadata = Self::generate_android_metadata(device_id, os_version, density)?;
+        self.register_device(metadata)
+    }
+
+    /// Создает iOS устройство со случайной реальной моделью iPhone: метрики экрана, память,
+    /// ядра CPU и версия iOS берутся из профиля модели.
+    /// С `seed` результат воспроизводим (для отладки)
+    pub async fn create_randomized_ios_device(&mut self, device_id: &str, seed: Option<u64>) -> Result<()> {
+        let mut rng = seeded_rng(seed);
+        let model = IOS_MODELS.choose(&mut rng).ok_or_else(|| anyhow!("Пустой пул моделей iOS"))?;
+        let os_version = model.os_versions.choose(&mut rng).copied().unwrap_or(DEFAULT_IOS_VERSION);
+
+        let mut metadata = Self::generate_ios_metadata(device_id, os_version)?;
+        model.apply(&mut metadata, &mut rng);
+        info!("Устройство {}: {} (iOS {})", device_id, model.name, os_version);
+        self.register_device(metadata)
+    }
+
+    /// Создает Android устройство со случайной реальной моделью; код модели в user agent
+    /// совпадает с `hardware_info.model`. С `seed` результат воспроизводим (для отладки)
+    pub async fn create_randomized_android_device(&mut self, device_id: &str, seed: Option<u64>) -> Result<()> {
+        let mut rng = seeded_rng(seed);
+        let model = ANDROID_MODELS.choose(&mut rng).ok_or_else(|| anyhow!("Пустой пул моделей Android"))?;
+        let os_version = model.os_versions.choose(&mut rng).copied().unwrap_or(DEFAULT_ANDROID_VERSION);
+
+        let mut metadata = Self::generate_android_metadata(device_id, os_version, None)?;
+        metadata.user_agent = metadata.user_agent.replace("SM-G998B", model.ua_model);
+        model.apply(&mut metadata, &mut rng);
+        info!("Устройство {}: {} (Android {})", device_id, model.name, os_version);
+        self.register_device(metadata)
+    }
+
+    /// Проверяет метаданные и регистрирует устройство с браузером по платформе:
+    /// WebKit для iOS, Chromium для Android. Режим контекста из окружения применяется,
+    /// только если профиль не задает его сам
+    fn register_device(&mut self, mut metadata: DeviceMetadata) -> Result<()> {
+        metadata.prepare()?;
+        let browser = EmulatedBrowser::for_metadata(&metadata)?;
+
+        let device_id = metadata.device_id.clone();
+        self.insert_device(
+            &device_id,
             EmulatedDevice {
-                metadata: metadata.clone(),
-                browser: EmulatedBrowser::ChromiumBased(chrome_config),
+                metadata,
+                browser,
+                automation_timeout: None,
             },
         );
         Ok(())
     }
 
-    fn generate_ios_metadata(&self, device_id: &str) -> Result<DeviceMetadata> {
+    /// Регистрирует готовое устройство (например из `EmulatedDeviceBuilder`) под его device_id.
+    /// Метаданные проверяются повторно: поля устройства публичные и могли измениться после сборки
+    pub fn register(&mut self, mut device: EmulatedDevice) -> Result<()> {
+        device.metadata.prepare()?;
+        let device_id = device.metadata.device_id.clone();
+        self.insert_device(&device_id, device);
+        Ok(())
+    }
+
+    /// Загружает профили устройств из файла вместо встроенных: JSON массив `DeviceMetadata`
+    /// или TOML (по расширению `.toml`) с массивом `[[devices]]`. Каждый профиль регистрируется
+    /// под своим `device_id`; ошибка разбора или проверки указывает номер записи
+    pub fn load_profiles_from_path(&mut self, path: &Path) -> Result<()> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Не удалось прочитать профили устройств {}: {}", path.display(), e))?;
+
+        let is_toml = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("toml"));
+        let entries: Vec<Value> = if is_toml {
+            let document: toml::Value = toml::from_str(&content)
+                .map_err(|e| anyhow!("Ошибка разбора {}: {}", path.display(), e))?;
+            let devices = document
+                .get("devices")
+                .cloned()
+                .ok_or_else(|| anyhow!("{}: нет массива [[devices]]", path.display()))?;
+            serde_json::from_value(serde_json::to_value(devices)?)
+                .map_err(|_| anyhow!("{}: devices должен быть массивом таблиц", path.display()))?
+        } else {
+            serde_json::from_str(&content)
+                .map_err(|e| anyhow!("{}: ожидается JSON массив профилей: {}", path.display(), e))?
+        };
+
+        let mut seen = std::collections::HashSet::new();
+        for (index, entry) in entries.into_iter().enumerate() {
+            let label = entry
+                .get("device_id")
+                .and_then(Value::as_str)
+                .map(|device_id| format!("#{} ({})", index, device_id))
+                .unwrap_or_else(|| format!("#{}", index));
+
+            let metadata: DeviceMetadata = serde_json::from_value(entry)
+                .map_err(|e| anyhow!("{}: профиль {}: {}", path.display(), label, e))?;
+            if !seen.insert(metadata.device_id.clone()) {
+                return Err(anyhow!("{}: профиль {}: повторяющийся device_id", path.display(), label));
+            }
+            self.register_device(metadata)
+                .map_err(|e| anyhow!("{}: профиль {}: {}", path.display(), label, e))?;
+        }
+
+        info!("Загружено профилей устройств: {} из {}", seen.len(), path.display());
+        Ok(())
+    }
+
+    /// Идентификаторы зарегистрированных устройств в отсортированном порядке
+    pub fn device_ids(&self) -> Vec<String> {
+        let mut device_ids: Vec<String> = self.devices.keys().cloned().collect();
+        device_ids.sort();
+        device_ids
+    }
+
+    /// Копия метаданных устройства без блокировок — для кода, уже держащего guard GLOBAL_DEVICES
+    pub fn device_metadata(&self, device_id: &str) -> Result<DeviceMetadata> {
+        self.touch_existing(device_id);
+        self.devices.get(device_id)
+            .map(|device| device.metadata.clone())
+            .ok_or_else(|| anyhow!("Device not found"))
+    }
+
+    /// Копия устройства целиком без блокировок — для кода, уже держащего guard GLOBAL_DEVICES
+    pub fn device(&self, device_id: &str) -> Result<EmulatedDevice> {
+        self.touch_existing(device_id);
+        self.devices.get(device_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("Device not found"))
+    }
+
+    /// Копия браузера устройства без блокировок — для кода, уже держащего guard GLOBAL_DEVICES
+    pub fn device_browser(&self, device_id: &str) -> Result<EmulatedBrowser> {
+        self.touch_existing(device_id);
+        self.devices.get(device_id)
+            .map(|device| device.browser.clone())
+            .ok_or_else(|| anyhow!("Device not found"))
+    }
+
+    /// Таймаут автоматизации устройства, если он переопределен
+    pub fn automation_timeout(&self, device_id: &str) -> Result<Option<Duration>> {
+        self.touch_existing(device_id);
+        self.devices.get(device_id)
+            .map(|device| device.automation_timeout)
+            .ok_or_else(|| anyhow!("Device not found"))
+    }
+
+    fn touch_existing(&self, device_id: &str) {
+        if self.devices.contains_key(device_id) {
+            self.touch(device_id);
+        }
+    }
+
+    /// Проверяет доступность прокси пула (`ProxyPool::filter_healthy`) и назначает живые
+    /// устройствам по кругу в порядке device_id. Пустой после проверки пул — ошибка,
+    /// чтобы не запускать автоматизацию без прокси
+    pub async fn assign_proxies(&mut self, pool: &mut ProxyPool) -> Result<()> {
+        pool.filter_healthy(ProxyPool::health_timeout()).await;
+        if pool.is_empty() {
+            return Err(anyhow!("В пуле нет доступных прокси"));
+        }
+
+        let mut device_ids: Vec<String> = self.devices.keys().cloned().collect();
+        device_ids.sort();
+
+        for (device_id, proxy) in device_ids.iter().zip(pool.proxies().iter().cycle()) {
+            if let Some(device) = self.devices.get_mut(device_id) {
+                info!("Устройству {} назначен прокси {}", device_id, proxy.address());
+                device.metadata.connection_info.proxy = Some(proxy.clone());
+                self.emit(DeviceEvent::Updated(device_id.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Назначает устройствам локали из пула: язык, lang_code и часовой пояс берутся из одной
+    /// записи, чтобы язык и время устройства не противоречили друг другу
+    pub fn assign_locales(&mut self, pool: &[LocaleProfile], strategy: LocaleStrategy) -> Result<()> {
+        if pool.is_empty() {
+            return Err(anyhow!("Пул локалей пуст"));
+        }
+        let pool = pool
+            .iter()
+            .map(|locale| {
+                if locale.language.trim().is_empty() {
+                    return Err(anyhow!("Пустой язык в пуле локалей"));
+                }
+                Ok(LocaleProfile {
+                    language: locale.language.trim().to_string(),
+                    timezone: normalize_timezone(&locale.timezone)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut device_ids: Vec<String> = self.devices.keys().cloned().collect();
+        device_ids.sort();
+
+        let mut rng = rand::thread_rng();
+        for (index, device_id) in device_ids.iter().enumerate() {
+            let locale = match strategy {
+                LocaleStrategy::RoundRobin => &pool[index % pool.len()],
+                LocaleStrategy::Random => pool.choose(&mut rng).unwrap_or(&pool[0]),
+            };
+            if let Some(device) = self.devices.get_mut(device_id) {
+                info!("Устройству {} назначена локаль {} ({})", device_id, locale.language, locale.timezone);
+                device.metadata.language = locale.language.clone();
+                device.metadata.lang_code = locale.lang_code();
+                device.metadata.timezone = locale.timezone.clone();
+                self.emit(DeviceEvent::Updated(device_id.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Опции контекста Playwright для всех зарегистрированных устройств (device_id -> опции).
+    /// Позволяет Python стороне создать контексты всего парка за один вызов через FFI
+    pub fn export_all_context_options(&self) -> HashMap<String, Value> {
+        self.devices
+            .iter()
+            .map(|(device_id, device)| (device_id.clone(), device.to_playwright_context_options()))
+            .collect()
+    }
+
+    fn generate_ios_metadata(device_id: &str, os_version: &str) -> Result<DeviceMetadata> {
+        let os_version = parse_os_version(os_version)?;
         Ok(DeviceMetadata {
             device_id: device_id.to_string(),
             platform: PlatformType::IOS,
             app_version: "11.3.1".to_string(),
-            user_agent: "Mozilla/5.0 (iPhone; CPU iPhone OS 16_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/16.0 Mobile/15E148 Safari/604.1".to_string(),
+            user_agent: format!(
+                "Mozilla/5.0 (iPhone; CPU iPhone OS {} like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/{} Mobile/15E148 Safari/604.1",
+                os_version.replace('.', "_"),
+                safari_version(&os_version),
+            ),
             screen_metrics: ScreenMetrics {
                 width: 390,
                 height: 844,
                 pixel_ratio: 3.0,
                 touch_points: 5,
+                // Safari съедает статус-бар, адресную строку и панель навигации
+                viewport: Size { width: 390, height: 664 },
+                window: Size { width: 390, height: 844 },
             },
             language: "en-US".to_string(),
             lang_code: "en".to_string(),
@@ -166,16 +1108,16 @@ impl DeviceManager {
             webview_data: WebViewData {
                 engine_version: "605.1.15".to_string(),
                 supported_apis: vec![
-                    "WebKit".to_string(),
-                    "WebGL".to_string(),
-                    "WebRTC".to_string(),
+                    WebApi::WebKit,
+                    WebApi::WebGL,
+                    WebApi::WebRTC,
                 ],
                 webkit_flags: Some(WebKitFlags::default()),
                 chrome_flags: None,
             },
             hardware_info: HardwareInfo {
                 model: "iPhone 14 Pro".to_string(),
-                platform_version: "iOS 11.3.1".to_string(),
+                platform_version: format!("iOS {}", os_version),
                 memory: "6GB".to_string(),
                 cpu_cores: 6,
                 gpu_renderer: "Apple GPU".to_string(),
@@ -185,38 +1127,58 @@ impl DeviceManager {
                 bandwidth: "10mbps".to_string(),
                 rtt: 50,
                 throughput: 1000,
+                proxy: None,
+                save_data: false,
             },
+            permissions: default_permissions(),
+            geolocation: None,
+            extra_headers: HashMap::new(),
+            client_hints: None,
+            context_mode: ContextMode::default(),
+            user_data_dir: None,
         })
     }
 
-    fn generate_android_metadata(&self, device_id: &str) -> Result<DeviceMetadata> {
+    fn generate_android_metadata(
+        device_id: &str,
+        os_version: &str,
+        density: Option<DensityBucket>,
+    ) -> Result<DeviceMetadata> {
+        let os_version = parse_os_version(os_version)?;
+        let screen_metrics = density.map(|bucket| bucket.screen_metrics()).unwrap_or(ScreenMetrics {
+            width: 412,
+            height: 915,
+            pixel_ratio: 2.625,
+            touch_points: 5,
+            // Без статус-бара, адресной строки Chrome и системной навигации
+            viewport: Size { width: 412, height: 915 - ANDROID_BROWSER_CHROME_HEIGHT },
+            window: Size { width: 412, height: 915 },
+        });
         Ok(DeviceMetadata {
             device_id: device_id.to_string(),
             platform: PlatformType::Android,
             app_version: "11.3.1".to_string(),
-            user_agent: "Mozilla/5.0 (Linux; Android 13; SM-G998B) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/97.0.4692.98 Mobile Safari/537.36".to_string(),
-            screen_metrics: ScreenMetrics {
-                width: 412,
-                height: 915,
-                pixel_ratio: 2.625,
-                touch_points: 5,
-            },
+            user_agent: format!(
+                "Mozilla/5.0 (Linux; Android {}; SM-G998B) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/97.0.4692.98 Mobile Safari/537.36",
+                os_version,
+            ),
+            screen_metrics,
             language: "en-US".to_string(),
             lang_code: "en".to_string(),
             timezone: "UTC".to_string(),
             webview_data: WebViewData {
                 engine_version: "97.0.4692.98".to_string(),
                 supported_apis: vec![
-                    "WebView".to_string(),
-                    "WebGL".to_string(),
-                    "WebRTC".to_string(),
+                    WebApi::WebView,
+                    WebApi::WebGL,
+                    WebApi::WebRTC,
                 ],
                 webkit_flags: None,
                 chrome_flags: Some(ChromeFlags::default()),
             },
             hardware_info: HardwareInfo {
                 model: "Samsung Galaxy S21 Ultra".to_string(),
-                platform_version: "Android 13".to_string(),
+                platform_version: format!("Android {}", os_version),
                 memory: "12GB".to_string(),
                 cpu_cores: 8,
                 gpu_renderer: "Adreno 660".to_string(),
@@ -226,92 +1188,1414 @@ impl DeviceManager {
                 bandwidth: "20mbps".to_string(),
                 rtt: 30,
                 throughput: 2000,
+                proxy: None,
+                save_data: false,
             },
+            permissions: default_permissions(),
+            geolocation: None,
+            extra_headers: HashMap::new(),
+            client_hints: None,
+            context_mode: ContextMode::default(),
+            user_data_dir: None,
         })
     }
 }
 
-impl EmulatedBrowser {
-    pub fn get_browser_config(&self, width: u32, height: u32) -> Result<BrowserConfig> {
-        let config = match self {
-            EmulatedBrowser::Webkit(webkit_config) => {
-                BrowserConfig::builder()
-                    .window_size(width, height)
-                    .arg(format!("--user-agent={}", webkit_config.user_agent))
-                    .arg("--disable-background-networking")
-                    .arg("--disable-background-timer-throttling")
-                    .arg("--disable-backgrounding-occluded-windows")
-                    .arg("--disable-breakpad")
-                    .arg("--disable-component-update")
-                    .arg("--disable-default-apps")
-                    .arg("--disable-dev-shm-usage")
-                    .arg("--disable-domain-reliability")
-                    .arg("--disable-extensions")
-                    .arg("--disable-features=AudioServiceOutOfProcess")
-                    .arg("--disable-hang-monitor")
-                    .arg("--disable-ipc-flooding-protection")
-                    .arg("--force-webview")
-                    .arg("--metrics-recording-only")
-                    .build()
-                    .map_err(|e| anyhow!(e))?
+/// Сборка собственного устройства поверх метаданных платформы по умолчанию (iPhone 14 Pro
+/// для iOS, Galaxy S21 Ultra для Android). Незаданные поля берутся из этих метаданных.
+///
+/// ```ignore
+/// let tablet = EmulatedDeviceBuilder::new("tablet")
+///     .platform(PlatformType::Android)
+///     .screen_metrics(tablet_metrics)
+///     .build()?;
+/// manager.register(tablet)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EmulatedDeviceBuilder {
+    device_id: String,
+    platform: PlatformType,
+    screen_metrics: Option<ScreenMetrics>,
+    user_agent: Option<String>,
+    hardware_info: Option<HardwareInfo>,
+    connection_info: Option<ConnectionInfo>,
+}
+
+impl EmulatedDeviceBuilder {
+    /// Новое устройство; платформа по умолчанию - Android
+    pub fn new(device_id: &str) -> Self {
+        Self {
+            device_id: device_id.to_string(),
+            platform: PlatformType::Android,
+            screen_metrics: None,
+            user_agent: None,
+            hardware_info: None,
+            connection_info: None,
+        }
+    }
+
+    pub fn platform(mut self, platform: PlatformType) -> Self {
+        self.platform = platform;
+        self
+    }
+
+    pub fn screen_metrics(mut self, screen_metrics: ScreenMetrics) -> Self {
+        self.screen_metrics = Some(screen_metrics);
+        self
+    }
+
+    /// User agent должен соответствовать платформе; версия ОС в `hardware_info`
+    /// выводится из него (см. `EmulatedDevice::set_user_agent`)
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    pub fn hardware_info(mut self, hardware_info: HardwareInfo) -> Self {
+        self.hardware_info = Some(hardware_info);
+        self
+    }
+
+    pub fn connection_info(mut self, connection_info: ConnectionInfo) -> Self {
+        self.connection_info = Some(connection_info);
+        self
+    }
+
+    /// Собирает устройство. Ошибка, если device_id пуст, метрики вырождены (нулевые размеры),
+    /// user agent другой платформы или параметры железа и сети не проходят проверку
+    pub fn build(self) -> Result<EmulatedDevice> {
+        if self.device_id.trim().is_empty() {
+            return Err(anyhow!("Пустой device_id"));
+        }
+
+        let mut metadata = match self.platform {
+            PlatformType::IOS => DeviceManager::generate_ios_metadata(&self.device_id, DEFAULT_IOS_VERSION)?,
+            PlatformType::Android => {
+                DeviceManager::generate_android_metadata(&self.device_id, DEFAULT_ANDROID_VERSION, None)?
+            }
+        };
+        if let Some(screen_metrics) = self.screen_metrics {
+            metadata.screen_metrics = screen_metrics;
+        }
+        if let Some(hardware_info) = self.hardware_info {
+            metadata.hardware_info = hardware_info;
+        }
+        if let Some(connection_info) = self.connection_info {
+            metadata.connection_info = connection_info;
+        }
+        metadata.prepare()?;
+
+        let browser = EmulatedBrowser::for_metadata(&metadata)?;
+        let mut device = EmulatedDevice {
+            metadata,
+            browser,
+            automation_timeout: None,
+        };
+        if let Some(user_agent) = self.user_agent {
+            device.set_user_agent(&user_agent, None)?;
+        }
+        Ok(device)
+    }
+}
+
+impl EmulatedDevice {
+    /// Опции для `browser.new_context(**options)` Playwright (имена в стиле Python API)
+    pub fn to_playwright_context_options(&self) -> Value {
+        let metrics = &self.metadata.screen_metrics;
+        let mut options = json!({
+            "user_agent": self.metadata.user_agent,
+            "viewport": {
+                "width": metrics.viewport.width,
+                "height": metrics.viewport.height,
+            },
+            "screen": {
+                "width": metrics.width,
+                "height": metrics.height,
             },
+            "device_scale_factor": metrics.pixel_ratio,
+            "is_mobile": true,
+            "has_touch": metrics.touch_points > 0,
+            "locale": self.metadata.language,
+            "timezone_id": self.metadata.timezone,
+            "permissions": self.granted_permissions(),
+            "extra_http_headers": self.metadata.effective_extra_headers(),
+            "geolocation": self.metadata.geolocation,
+        });
+        // Прокси с авторизацией задается на уровне контекста, где Playwright сам отвечает на запрос учетных данных
+        if let Some(proxy) = self.metadata.connection_info.proxy.as_ref().filter(|proxy| proxy.has_credentials()) {
+            options["proxy"] = proxy.to_playwright_proxy();
+        }
+        options
+    }
+
+    /// Опции для `chromium.launch(**options)` Playwright; пустой объект, если переопределений нет
+    pub fn to_playwright_launch_options(&self) -> Value {
+        let mut options = serde_json::Map::new();
+        if let Some(executable) = self.browser.browser_executable() {
+            options.insert("executable_path".to_string(), json!(executable.to_string_lossy()));
+        }
+        if let Some(proxy) = self.metadata.connection_info.proxy.as_ref().filter(|proxy| !proxy.has_credentials()) {
+            options.insert("proxy".to_string(), proxy.to_playwright_proxy());
+        }
+        let args = self.browser.playwright_args();
+        if !args.is_empty() {
+            options.insert("args".to_string(), json!(args));
+        }
+        Value::Object(options)
+    }
+
+    /// Init script для `context.add_init_script`: подменяет `navigator.hardwareConcurrency`
+    /// и `navigator.deviceMemory` значениями из `HardwareInfo`, иначе JS видит параметры хоста
+    pub fn hardware_init_script(&self) -> Result<String> {
+        let hardware = &self.metadata.hardware_info;
+        Ok(format!(
+            r#"(() => {{
+    const override = (name, value) => Object.defineProperty(Navigator.prototype, name, {{
+        get: () => value,
+        configurable: true,
+    }});
+    override("hardwareConcurrency", {});
+    override("deviceMemory", {});
+}})();"#,
+            hardware.cpu_cores,
+            hardware.device_memory()?,
+        ))
+    }
+
+    /// Заменяет user agent устройства, сохраняя согласованность с client hints.
+    /// Без `hints` они выводятся из нового user agent; переданные hints проверяются на
+    /// соответствие user agent. platform_version устройства обновляется под новую версию ОС
+    pub fn set_user_agent(&mut self, user_agent: &str, hints: Option<ClientHints>) -> Result<()> {
+        let derived = ClientHints::from_user_agent(user_agent)?;
+        let hints = match hints {
+            Some(hints) => {
+                hints.validate_against(&derived)?;
+                hints
+            }
+            None => derived,
+        };
+
+        let expected_platform = match self.metadata.platform {
+            PlatformType::IOS => "iOS",
+            PlatformType::Android => "Android",
+        };
+        if hints.platform != expected_platform {
+            return Err(anyhow!(
+                "User agent платформы {} нельзя установить устройству {} ({})",
+                hints.platform,
+                self.metadata.device_id,
+                expected_platform
+            ));
+        }
+
+        let mut metadata = self.metadata.clone();
+        metadata.user_agent = user_agent.to_string();
+        metadata.hardware_info.platform_version = format!("{} {}", hints.platform, hints.platform_version);
+        metadata.validate_os_version()?;
+        metadata.client_hints = Some(hints);
+        self.metadata = metadata;
+
+        match &mut self.browser {
+            EmulatedBrowser::Webkit(webkit_config) => {
+                webkit_config.user_agent = user_agent.to_string();
+                webkit_config.platform_version = self.metadata.hardware_info.platform_version.clone();
+            }
             EmulatedBrowser::ChromiumBased(chrome_config) => {
-                BrowserConfig::builder()
-                    .window_size(width, height)
-                    .arg(format!("--user-agent={}", chrome_config.user_agent))
-                    .arg("--disable-background-networking")
-                    .arg("--disable-background-timer-throttling")
-                    .arg("--disable-backgrounding-occluded-windows")
-                    .arg("--disable-breakpad")
-                    .arg("--disable-component-update")
-                    .arg("--disable-default-apps")
-                    .arg("--disable-dev-shm-usage")
-                    .arg("--disable-domain-reliability")
-                    .arg("--disable-extensions")
-                    .arg("--disable-features=AudioServiceOutOfProcess")
-                    .arg("--disable-hang-monitor")
-                    .arg("--disable-ipc-flooding-protection")
-                    .arg("--force-webview")
-                    .arg("--metrics-recording-only")
-                    .build()
-                    .map_err(|e| anyhow!(e))?
+                chrome_config.user_agent = user_agent.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    /// Client hints устройства: заданные явно или выведенные из текущего user agent
+    pub fn client_hints(&self) -> Result<ClientHints> {
+        match &self.metadata.client_hints {
+            Some(hints) => Ok(hints.clone()),
+            None => ClientHints::from_user_agent(&self.metadata.user_agent),
+        }
+    }
+
+    /// Init script, подменяющий `navigator.connection.saveData` при включенном save_data
+    pub fn save_data_init_script(&self) -> Option<String> {
+        if !self.metadata.connection_info.save_data {
+            return None;
+        }
+        Some(r#"(() => {
+    if (navigator.connection) {
+        Object.defineProperty(Object.getPrototypeOf(navigator.connection), "saveData", {
+            get: () => true,
+            configurable: true,
+        });
+    }
+})();"#.to_string())
+    }
+
+    /// Init script, подменяющий `UNMASKED_VENDOR_WEBGL`/`UNMASKED_RENDERER_WEBGL`
+    /// (`WEBGL_debug_renderer_info`) на GPU эмулируемого устройства
+    pub fn webgl_init_script(&self) -> Option<String> {
+        if !self.browser.fingerprint().spoof_webgl {
+            return None;
+        }
+        let hardware = &self.metadata.hardware_info;
+        Some(format!(
+            r#"(() => {{
+    const vendor = {};
+    const renderer = {};
+    for (const context of [window.WebGLRenderingContext, window.WebGL2RenderingContext]) {{
+        if (!context) continue;
+        const getParameter = context.prototype.getParameter;
+        context.prototype.getParameter = function (parameter) {{
+            if (parameter === 0x9245) return vendor;
+            if (parameter === 0x9246) return renderer;
+            return getParameter.call(this, parameter);
+        }};
+    }}
+}})();"#,
+            json!(hardware.gpu_vendor()),
+            json!(hardware.gpu_renderer),
+        ))
+    }
+
+    /// Init script шума canvas: при чтении пикселей (`toDataURL`, `toBlob`, `getImageData`)
+    /// младший бит части каналов меняется. Зерно выводится из device_id, поэтому отпечаток
+    /// стабилен для устройства между запусками, но различается между устройствами
+    pub fn canvas_noise_init_script(&self) -> Option<String> {
+        if !self.browser.fingerprint().canvas_noise {
+            return None;
+        }
+        let seed = self
+            .metadata
+            .device_id
+            .bytes()
+            .fold(0x811c9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x01000193));
+        Some(format!(
+            r#"(() => {{
+    const seed = {};
+    const noise = (data) => {{
+        let state = seed;
+        for (let i = 0; i < data.length; i += 4) {{
+            state = (Math.imul(state, 1103515245) + 12345) >>> 0;
+            if ((state >>> 16) % 16 === 0) data[i + (state % 3)] ^= 1;
+        }}
+    }};
+    const getImageData = CanvasRenderingContext2D.prototype.getImageData;
+    CanvasRenderingContext2D.prototype.getImageData = function (...args) {{
+        const image = getImageData.apply(this, args);
+        noise(image.data);
+        return image;
+    }};
+    const withNoise = (canvas) => {{
+        const context = canvas.getContext("2d");
+        if (!context || !canvas.width || !canvas.height) return canvas;
+        const copy = document.createElement("canvas");
+        copy.width = canvas.width;
+        copy.height = canvas.height;
+        const copyContext = copy.getContext("2d");
+        copyContext.drawImage(canvas, 0, 0);
+        const image = getImageData.call(copyContext, 0, 0, copy.width, copy.height);
+        noise(image.data);
+        copyContext.putImageData(image, 0, 0);
+        return copy;
+    }};
+    const toDataURL = HTMLCanvasElement.prototype.toDataURL;
+    HTMLCanvasElement.prototype.toDataURL = function (...args) {{
+        return toDataURL.apply(withNoise(this), args);
+    }};
+    const toBlob = HTMLCanvasElement.prototype.toBlob;
+    HTMLCanvasElement.prototype.toBlob = function (...args) {{
+        return toBlob.apply(withNoise(this), args);
+    }};
+}})();"#,
+            seed,
+        ))
+    }
+
+    /// Все init scripts устройства в порядке применения через `context.add_init_script`
+    pub fn init_scripts(&self) -> Result<Vec<String>> {
+        let mut scripts = vec![self.hardware_init_script()?];
+        scripts.extend(self.save_data_init_script());
+        scripts.extend(self.webgl_init_script());
+        scripts.extend(self.canvas_noise_init_script());
+        Ok(scripts)
+    }
+
+    /// Итоговые состояния разрешений: явно заданные в `permissions` плюс geolocation→granted,
+    /// если задана геопозиция и состояние geolocation не указано явно
+    pub fn effective_permissions(&self) -> HashMap<String, PermissionState> {
+        let mut permissions = self.metadata.permissions.clone();
+        if self.metadata.geolocation.is_some() {
+            permissions
+                .entry("geolocation".to_string())
+                .or_insert(PermissionState::Granted);
+        }
+        permissions
+    }
+
+    /// Разрешения для `permissions` контекста Playwright / CDP `Browser.grantPermissions`.
+    /// Denied и prompt Playwright выдать не умеет - они применяются отдельно, см. `permission_overrides`
+    pub fn granted_permissions(&self) -> Vec<String> {
+        let mut granted: Vec<String> = self
+            .effective_permissions()
+            .into_iter()
+            .filter(|(_, state)| *state == PermissionState::Granted)
+            .map(|(name, _)| name)
+            .collect();
+        granted.sort();
+        granted
+    }
+
+    /// Явные состояния denied и prompt (имя -> "denied" | "prompt") для CDP `Browser.setPermission`:
+    /// без них разрешение осталось бы в состоянии браузера по умолчанию, а не в заданном профилем
+    pub fn permission_overrides(&self) -> BTreeMap<String, PermissionState> {
+        self.effective_permissions()
+            .into_iter()
+            .filter(|(_, state)| *state != PermissionState::Granted)
+            .collect()
+    }
+}
+
+// Модели устройств, совпадающие с дескрипторами Playwright (`playwright.devices[name]`)
+const PLAYWRIGHT_DESCRIPTORS: &[(&str, &str)] = &[
+    ("iPhone 12", "iPhone 12"),
+    ("iPhone 12 Pro", "iPhone 12 Pro"),
+    ("iPhone 13", "iPhone 13"),
+    ("iPhone 13 Pro", "iPhone 13 Pro"),
+    ("iPhone 14", "iPhone 14"),
+    ("iPhone 14 Pro", "iPhone 14 Pro"),
+    ("iPhone 14 Pro Max", "iPhone 14 Pro Max"),
+    ("iPhone 15", "iPhone 15"),
+    ("iPhone 15 Pro", "iPhone 15 Pro"),
+    ("Pixel 5", "Pixel 5"),
+    ("Pixel 7", "Pixel 7"),
+    ("Samsung Galaxy S8", "Galaxy S8"),
+    ("Samsung Galaxy S9+", "Galaxy S9+"),
+];
+
+impl DeviceMetadata {
+    /// Нормализует часовой пояс, подставляет режим контекста из окружения (если профиль
+    /// оставил значения по умолчанию) и проверяет метаданные перед регистрацией
+    fn prepare(&mut self) -> Result<()> {
+        self.timezone = normalize_timezone(&self.timezone)?;
+        self.screen_metrics.validate()?;
+        self.validate_os_version()?;
+        self.connection_info.validate()?;
+        self.hardware_info.validate()?;
+        self.validate_extra_headers()?;
+        if self.context_mode == ContextMode::Incognito && self.user_data_dir.is_none() {
+            (self.context_mode, self.user_data_dir) = context_mode_from_env(&self.device_id)?;
+        }
+        self.validate_context_mode()
+    }
+
+    /// Persistent контекст без директории профиля запустить нельзя
+    pub fn validate_context_mode(&self) -> Result<()> {
+        if self.context_mode == ContextMode::Persistent && self.user_data_dir.is_none() {
+            return Err(anyhow!(
+                "Устройство {}: режим persistent требует user_data_dir",
+                self.device_id
+            ));
+        }
+        Ok(())
+    }
+
+    /// Проверяет имена дополнительных заголовков (token по RFC 7230)
+    pub fn validate_extra_headers(&self) -> Result<()> {
+        for name in self.extra_headers.keys() {
+            let valid = !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c));
+            if !valid {
+                return Err(anyhow!("Некорректное имя HTTP заголовка: '{}'", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Дополнительные заголовки без управляемых Chromium (Host, Content-Length, ...),
+    /// которые пропускаются с предупреждением
+    pub fn effective_extra_headers(&self) -> HashMap<String, String> {
+        let mut headers: HashMap<String, String> = self.extra_headers
+            .iter()
+            .filter(|(name, _)| {
+                let managed = MANAGED_HEADERS.contains(&name.to_lowercase().as_str());
+                if managed {
+                    warn!("Заголовок {} управляется браузером и не будет переопределен для {}", name, self.device_id);
+                }
+                !managed
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        if self.connection_info.save_data
+            && !headers.keys().any(|name| name.eq_ignore_ascii_case("save-data"))
+        {
+            headers.insert("Save-Data".to_string(), "on".to_string());
+        }
+        headers
+    }
+
+    /// Имя дескриптора Playwright для модели устройства, если он есть. Python сторона берет
+    /// `playwright.devices[name]` за основу и переопределяет только наши отличия; без
+    /// дескриптора используются полностью ручные опции `to_playwright_context_options`
+    pub fn playwright_descriptor_name(&self) -> Option<&'static str> {
+        PLAYWRIGHT_DESCRIPTORS
+            .iter()
+            .find(|(model, _)| *model == self.hardware_info.model)
+            .map(|(_, descriptor)| *descriptor)
+    }
+
+    /// Проверяет, что версия ОС в user agent совпадает с `hardware_info.platform_version`
+    pub fn validate_os_version(&self) -> Result<()> {
+        let (prefix, ua_token) = match self.platform {
+            PlatformType::IOS => {
+                let version = self.hardware_info.platform_version
+                    .strip_prefix("iOS ")
+                    .ok_or_else(|| anyhow!("Неверный формат platform_version: {}", self.hardware_info.platform_version))?;
+                ("iOS", format!("OS {} like Mac OS X", version.replace('.', "_")))
+            },
+            PlatformType::Android => {
+                let version = self.hardware_info.platform_version
+                    .strip_prefix("Android ")
+                    .ok_or_else(|| anyhow!("Неверный формат platform_version: {}", self.hardware_info.platform_version))?;
+                ("Android", format!("Android {};", version))
+            },
+        };
+
+        if !self.user_agent.contains(&ua_token) {
+            return Err(anyhow!(
+                "Версия {} в user agent не совпадает с platform_version '{}' для устройства {}",
+                prefix,
+                self.hardware_info.platform_version,
+                self.device_id
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl HardwareInfo {
+    /// Значение `navigator.deviceMemory` для `memory` ("6GB" -> 4): по спецификации Device Memory
+    /// браузер округляет объем вниз до степени двойки и ограничивает диапазоном 0.25..=8
+    pub fn device_memory(&self) -> Result<f64> {
+        let normalized = self.memory.trim().to_lowercase();
+        let value = normalized
+            .strip_suffix("gb")
+            .ok_or_else(|| anyhow!("Объем памяти '{}' должен быть в GB", self.memory))?
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| anyhow!("Некорректный объем памяти: '{}'", self.memory))?;
+        if value <= 0.0 {
+            return Err(anyhow!("Объем памяти должен быть положительным: '{}'", self.memory));
+        }
+
+        let rounded = 2f64.powi(value.log2().floor() as i32);
+        Ok(rounded.clamp(0.25, 8.0))
+    }
+
+    /// WebGL vendor, который реальные браузеры сообщают для `gpu_renderer`
+    pub fn gpu_vendor(&self) -> &'static str {
+        let renderer = self.gpu_renderer.to_lowercase();
+        if renderer.starts_with("apple") {
+            "Apple Inc."
+        } else if renderer.starts_with("adreno") {
+            "Qualcomm"
+        } else if renderer.starts_with("mali") {
+            "ARM"
+        } else if renderer.starts_with("powervr") {
+            "Imagination Technologies"
+        } else {
+            "Google Inc."
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if !(1..=32).contains(&self.cpu_cores) {
+            return Err(anyhow!("Неправдоподобное количество ядер CPU: {}", self.cpu_cores));
+        }
+        self.device_memory()?;
+        Ok(())
+    }
+}
+
+impl ConnectionInfo {
+    /// Пропускная способность в байтах в секунду, как ожидает CDP `Network.emulateNetworkConditions`
+    pub fn throughput_bytes_per_sec(&self) -> Result<f64> {
+        Ok(parse_bandwidth(&self.bandwidth)? as f64 / 8.0)
+    }
+
+    /// Тип соединения CDP по `network_type` ("wifi", "4g", ...); 5G в CDP нет, ближайший - 4G
+    pub fn connection_type(&self) -> ConnectionType {
+        match self.network_type.trim().to_lowercase().as_str() {
+            "2g" => ConnectionType::Cellular2g,
+            "3g" => ConnectionType::Cellular3g,
+            "4g" | "lte" | "5g" => ConnectionType::Cellular4g,
+            "wifi" => ConnectionType::Wifi,
+            "ethernet" => ConnectionType::Ethernet,
+            "none" | "offline" => ConnectionType::None,
+            _ => ConnectionType::Other,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        parse_bandwidth(&self.bandwidth)?;
+        Ok(())
+    }
+}
+
+/// Разбирает строку пропускной способности ("10mbps", "512kbps", "1.5gbps") в биты в секунду
+pub fn parse_bandwidth(bandwidth: &str) -> Result<u64> {
+    let normalized = bandwidth.trim().to_lowercase();
+    let split_at = normalized
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .ok_or_else(|| anyhow!("Не указана единица пропускной способности в '{}' (ожидается kbps, mbps или gbps)", bandwidth))?;
+    let (value, unit) = normalized.split_at(split_at);
+
+    let multiplier: u64 = match unit.trim() {
+        "bps" => 1,
+        "kbps" => 1_000,
+        "mbps" => 1_000_000,
+        "gbps" => 1_000_000_000,
+        other => {
+            return Err(anyhow!(
+                "Неизвестная единица пропускной способности '{}' в '{}' (ожидается kbps, mbps или gbps)",
+                other,
+                bandwidth
+            ))
+        }
+    };
+
+    let value: f64 = value
+        .parse()
+        .map_err(|_| anyhow!("Некорректное значение пропускной способности в '{}'", bandwidth))?;
+    if value <= 0.0 || !value.is_finite() {
+        return Err(anyhow!("Пропускная способность должна быть положительной: '{}'", bandwidth));
+    }
+
+    Ok((value * multiplier as f64).round() as u64)
+}
+
+/// Проверяет строку версии ОС вида "16", "16.0" или "16.4.1"
+fn parse_os_version(os_version: &str) -> Result<String> {
+    let os_version = os_version.trim();
+    let valid = !os_version.is_empty()
+        && os_version.split('.').count() <= 3
+        && os_version.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()));
+
+    if !valid {
+        return Err(anyhow!("Некорректная версия ОС: '{}'", os_version));
+    }
+    Ok(os_version.to_string())
+}
+
+// Синонимы UTC, которые приводятся к каноническому "UTC"
+const UTC_ALIASES: &[&str] = &[
+    "UTC", "Etc/UTC", "Etc/UCT", "UCT", "GMT", "Etc/GMT", "Etc/GMT+0", "Etc/GMT-0", "Etc/GMT0",
+    "GMT+0", "GMT-0", "GMT0", "Etc/Greenwich", "Greenwich", "Etc/Universal", "Universal",
+    "Etc/Zulu", "Zulu",
+];
+
+/// Проверяет часовой пояс по базе IANA (chrono-tz) и возвращает каноническое имя:
+/// регистр исправляется ("europe/moscow" -> "Europe/Moscow"), синонимы UTC сводятся к "UTC".
+/// Для неизвестного пояса ошибка содержит ближайшие совпадения
+pub fn normalize_timezone(timezone: &str) -> Result<String> {
+    let trimmed = timezone.trim();
+
+    if UTC_ALIASES.iter().any(|alias| alias.eq_ignore_ascii_case(trimmed)) {
+        return Ok("UTC".to_string());
+    }
+
+    if let Some(zone) = TZ_VARIANTS.iter().find(|zone| zone.name().eq_ignore_ascii_case(trimmed)) {
+        return Ok(zone.name().to_string());
+    }
+
+    let wanted = trimmed.to_lowercase();
+    let mut candidates: Vec<(usize, &str)> = TZ_VARIANTS
+        .iter()
+        .map(|zone| (levenshtein(&wanted, &zone.name().to_lowercase()), zone.name()))
+        .collect();
+    candidates.sort();
+    let suggestions: Vec<&str> = candidates.iter().take(3).map(|(_, name)| *name).collect();
+
+    Err(anyhow!(
+        "Неизвестный часовой пояс '{}'. Возможно, имелось в виду: {}",
+        timezone,
+        suggestions.join(", ")
+    ))
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Путь к собственному бинарнику браузера из BROWSER_EXECUTABLE. Путь должен указывать
+/// на существующий файл, иначе создание устройства завершается ошибкой
+pub fn browser_executable_from_env() -> Result<Option<PathBuf>> {
+    let path = match std::env::var("BROWSER_EXECUTABLE") {
+        Ok(value) if !value.trim().is_empty() => PathBuf::from(value.trim()),
+        _ => return Ok(None),
+    };
+
+    if !path.is_file() {
+        return Err(anyhow!("BROWSER_EXECUTABLE не найден: {}", path.display()));
+    }
+    Ok(Some(path))
+}
+
+/// Режим контекста из BROWSER_CONTEXT_MODE и профиль устройства `<BROWSER_USER_DATA_DIR>/<device_id>`
+fn context_mode_from_env(device_id: &str) -> Result<(ContextMode, Option<PathBuf>)> {
+    let mode = match std::env::var("BROWSER_CONTEXT_MODE") {
+        Ok(value) if !value.trim().is_empty() => value.parse::<ContextMode>()?,
+        _ => ContextMode::default(),
+    };
+    let user_data_dir = std::env::var("BROWSER_USER_DATA_DIR")
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .map(|value| PathBuf::from(value).join(device_id));
+    Ok((mode, user_data_dir))
+}
+
+/// Разрешения устройства по умолчанию: уведомления запрещены, остальное - по запросу
+fn default_permissions() -> HashMap<String, PermissionState> {
+    HashMap::from([("notifications".to_string(), PermissionState::Denied)])
+}
+
+/// Профиль реальной модели для случайных устройств. Метрики экрана и GPU фиксированы
+/// для модели, объем памяти выбирается из выпускавшихся вариантов
+struct ModelProfile {
+    name: &'static str,
+    /// Код модели в user agent Android ("SM-S911B"); у iPhone user agent модель не содержит
+    ua_model: &'static str,
+    /// Размер экрана в CSS пикселях
+    screen: Size,
+    pixel_ratio: f32,
+    /// Высота интерфейса браузера и системных панелей, не входящая в viewport
+    browser_chrome_height: u32,
+    memory: &'static [&'static str],
+    cpu_cores: u8,
+    gpu_renderer: &'static str,
+    /// Версии ОС, которые модель поддерживает (не ниже версии, с которой модель выпущена)
+    os_versions: &'static [&'static str],
+}
+
+impl ModelProfile {
+    fn apply(&self, metadata: &mut DeviceMetadata, rng: &mut StdRng) {
+        metadata.screen_metrics = ScreenMetrics {
+            width: self.screen.width,
+            height: self.screen.height,
+            pixel_ratio: self.pixel_ratio,
+            touch_points: 5,
+            viewport: Size {
+                width: self.screen.width,
+                height: self.screen.height.saturating_sub(self.browser_chrome_height),
             },
+            window: self.screen,
+        };
+        // Модель Android в user agent и hardware_info.model - один и тот же код ("SM-S911B"),
+        // user agent iPhone модели не содержит, поэтому у iOS остается название модели
+        metadata.hardware_info.model = match metadata.platform {
+            PlatformType::Android => self.ua_model,
+            PlatformType::IOS => self.name,
+        }
+        .to_string();
+        metadata.hardware_info.memory = self.memory[rng.gen_range(0..self.memory.len())].to_string();
+        metadata.hardware_info.cpu_cores = self.cpu_cores;
+        metadata.hardware_info.gpu_renderer = self.gpu_renderer.to_string();
+    }
+}
+
+// Safari: статус-бар, адресная строка и панель навигации
+const IOS_BROWSER_CHROME_HEIGHT: u32 = 180;
+
+const IOS_MODELS: &[ModelProfile] = &[
+    ModelProfile {
+        name: "iPhone SE (3rd generation)",
+        ua_model: "iPhone",
+        screen: Size { width: 375, height: 667 },
+        pixel_ratio: 2.0,
+        browser_chrome_height: 120,
+        memory: &["4GB"],
+        cpu_cores: 6,
+        gpu_renderer: "Apple GPU",
+        os_versions: IOS_16_AND_17,
+    },
+    ModelProfile {
+        name: "iPhone 13 mini",
+        ua_model: "iPhone",
+        screen: Size { width: 375, height: 812 },
+        pixel_ratio: 3.0,
+        browser_chrome_height: IOS_BROWSER_CHROME_HEIGHT,
+        memory: &["4GB"],
+        cpu_cores: 6,
+        gpu_renderer: "Apple GPU",
+        os_versions: IOS_16_AND_17,
+    },
+    ModelProfile {
+        name: "iPhone 13",
+        ua_model: "iPhone",
+        screen: Size { width: 390, height: 844 },
+        pixel_ratio: 3.0,
+        browser_chrome_height: IOS_BROWSER_CHROME_HEIGHT,
+        memory: &["4GB"],
+        cpu_cores: 6,
+        gpu_renderer: "Apple GPU",
+        os_versions: IOS_16_AND_17,
+    },
+    ModelProfile {
+        name: "iPhone 14 Pro",
+        ua_model: "iPhone",
+        screen: Size { width: 393, height: 852 },
+        pixel_ratio: 3.0,
+        browser_chrome_height: IOS_BROWSER_CHROME_HEIGHT,
+        memory: &["6GB"],
+        cpu_cores: 6,
+        gpu_renderer: "Apple GPU",
+        os_versions: IOS_16_AND_17,
+    },
+    ModelProfile {
+        name: "iPhone 14 Pro Max",
+        ua_model: "iPhone",
+        screen: Size { width: 430, height: 932 },
+        pixel_ratio: 3.0,
+        browser_chrome_height: IOS_BROWSER_CHROME_HEIGHT,
+        memory: &["6GB"],
+        cpu_cores: 6,
+        gpu_renderer: "Apple GPU",
+        os_versions: IOS_16_AND_17,
+    },
+    ModelProfile {
+        name: "iPhone 15 Pro",
+        ua_model: "iPhone",
+        screen: Size { width: 393, height: 852 },
+        pixel_ratio: 3.0,
+        browser_chrome_height: IOS_BROWSER_CHROME_HEIGHT,
+        memory: &["8GB"],
+        cpu_cores: 6,
+        gpu_renderer: "Apple GPU",
+        os_versions: &["17.1", "17.2", "17.4"],
+    },
+];
+
+// Версии iOS для моделей, выпущенных до iOS 17
+const IOS_16_AND_17: &[&str] = &["16.6", "17.1", "17.2", "17.4"];
+
+const ANDROID_MODELS: &[ModelProfile] = &[
+    ModelProfile {
+        name: "Samsung Galaxy S21 Ultra",
+        ua_model: "SM-G998B",
+        screen: Size { width: 412, height: 915 },
+        pixel_ratio: 2.625,
+        browser_chrome_height: ANDROID_BROWSER_CHROME_HEIGHT,
+        memory: &["12GB", "16GB"],
+        cpu_cores: 8,
+        gpu_renderer: "Adreno 660",
+        os_versions: &["12", "13", "14"],
+    },
+    ModelProfile {
+        name: "Samsung Galaxy S23",
+        ua_model: "SM-S911B",
+        screen: Size { width: 360, height: 780 },
+        pixel_ratio: 3.0,
+        browser_chrome_height: ANDROID_BROWSER_CHROME_HEIGHT,
+        memory: &["8GB"],
+        cpu_cores: 8,
+        gpu_renderer: "Adreno 740",
+        os_versions: &["13", "14"],
+    },
+    ModelProfile {
+        name: "Samsung Galaxy A53 5G",
+        ua_model: "SM-A536B",
+        screen: Size { width: 412, height: 915 },
+        pixel_ratio: 2.625,
+        browser_chrome_height: ANDROID_BROWSER_CHROME_HEIGHT,
+        memory: &["6GB", "8GB"],
+        cpu_cores: 8,
+        gpu_renderer: "Mali-G68",
+        os_versions: &["12", "13", "14"],
+    },
+    ModelProfile {
+        name: "Google Pixel 6a",
+        ua_model: "Pixel 6a",
+        screen: Size { width: 412, height: 915 },
+        pixel_ratio: 2.625,
+        browser_chrome_height: ANDROID_BROWSER_CHROME_HEIGHT,
+        memory: &["6GB"],
+        cpu_cores: 8,
+        gpu_renderer: "Mali-G78",
+        os_versions: &["12", "13", "14"],
+    },
+    ModelProfile {
+        name: "Google Pixel 7",
+        ua_model: "Pixel 7",
+        screen: Size { width: 412, height: 915 },
+        pixel_ratio: 2.625,
+        browser_chrome_height: ANDROID_BROWSER_CHROME_HEIGHT,
+        memory: &["8GB"],
+        cpu_cores: 8,
+        gpu_renderer: "Mali-G710",
+        os_versions: &["13", "14"],
+    },
+    ModelProfile {
+        name: "Xiaomi Redmi Note 11 Pro 5G",
+        ua_model: "2201116SG",
+        screen: Size { width: 393, height: 873 },
+        pixel_ratio: 2.75,
+        browser_chrome_height: ANDROID_BROWSER_CHROME_HEIGHT,
+        memory: &["6GB", "8GB"],
+        cpu_cores: 8,
+        gpu_renderer: "Adreno 619",
+        os_versions: &["12", "13"],
+    },
+];
+
+
+/// Генератор для случайных устройств: с `seed` детерминированный, иначе из энтропии ОС
+fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Флаги Chromium, общие для WebKit и Chromium устройств
+fn base_chromium_args() -> Vec<String> {
+    [
+        "--disable-background-networking",
+        "--disable-breakpad",
+        "--disable-component-update",
+        "--disable-default-apps",
+        "--disable-dev-shm-usage",
+        "--disable-domain-reliability",
+        "--disable-extensions",
+        "--metrics-recording-only",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Имя флага без значения: "--lang=ru" -> "--lang"
+fn flag_name(arg: &str) -> &str {
+    arg.split_once('=').map_or(arg, |(name, _)| name)
+}
+
+// Переключатели со списком features: Chromium учитывает только последний из одноименных,
+// поэтому их значения объединяются, а не заменяют друг друга
+const FEATURE_SWITCHES: [&str; 2] = ["--disable-features", "--enable-features"];
+
+fn is_feature_switch(arg: &str) -> bool {
+    FEATURE_SWITCHES.contains(&flag_name(arg))
+}
+
+/// Объединяет все `--disable-features` и `--enable-features` в один переключатель каждого вида
+/// на месте первого вхождения; повторяющиеся features не дублируются
+fn merge_feature_switches(args: Vec<String>) -> Vec<String> {
+    let mut features: HashMap<String, Vec<String>> = HashMap::new();
+    for arg in &args {
+        if let Some((name, list)) = arg.split_once('=').filter(|(name, _)| FEATURE_SWITCHES.contains(name)) {
+            let merged = features.entry(name.to_string()).or_default();
+            for feature in list.split(',').map(str::trim).filter(|feature| !feature.is_empty()) {
+                if !merged.iter().any(|known| known == feature) {
+                    merged.push(feature.to_string());
+                }
+            }
+        }
+    }
+
+    let mut merged_args = Vec::with_capacity(args.len());
+    for arg in args {
+        if !is_feature_switch(&arg) {
+            merged_args.push(arg);
+            continue;
+        }
+        if let Some(list) = features.remove(flag_name(&arg)) {
+            if !list.is_empty() {
+                merged_args.push(format!("{}={}", flag_name(&arg), list.join(",")));
+            }
+        }
+    }
+    merged_args
+}
+
+/// `args` с `extra_args` в конце: одноименный флаг из `extra_args` вытесняет флаг из `args`,
+/// кроме списков features, которые потом объединяет `merge_feature_switches`
+fn with_overrides(mut args: Vec<String>, extra_args: &[String]) -> Vec<String> {
+    args.retain(|arg| {
+        is_feature_switch(arg) || !extra_args.iter().any(|extra| flag_name(extra) == flag_name(arg))
+    });
+    args.extend(extra_args.iter().cloned());
+    args
+}
+
+/// Версия Safari в user agent совпадает с major.minor версией iOS
+fn safari_version(os_version: &str) -> String {
+    let mut parts = os_version.split('.');
+    let major = parts.next().unwrap_or("0");
+    let minor = parts.next().unwrap_or("0");
+    format!("{}.{}", major, minor)
+}
+
+impl EmulatedBrowser {
+    /// Браузер по платформе устройства: WebKit для iOS, Chromium для Android
+    fn for_metadata(metadata: &DeviceMetadata) -> Result<Self> {
+        Ok(match metadata.platform {
+            PlatformType::IOS => EmulatedBrowser::Webkit(WebKitConfig {
+                user_agent: metadata.user_agent.clone(),
+                webkit_version: "605.1.15".to_string(),
+                platform_version: metadata.hardware_info.platform_version.clone(),
+                build_number: "15E148".to_string(),
+                doh: DohConfig::default(),
+                browser_executable: browser_executable_from_env()?,
+                protocol: ProtocolConfig::from_env()?,
+                fingerprint: FingerprintConfig::from_env(),
+                extra_args: Vec::new(),
+            }),
+            PlatformType::Android => {
+                let engine_version = metadata.webview_data.engine_version.clone();
+                EmulatedBrowser::ChromiumBased(ChromiumConfig {
+                    user_agent: metadata.user_agent.clone(),
+                    // Build и patch части версии Chrome ("97.0.4692.98" -> "4692.98")
+                    build_version: engine_version.splitn(3, '.').nth(2).unwrap_or(&engine_version).to_string(),
+                    chrome_version: engine_version.clone(),
+                    webview_version: engine_version,
+                    force_webview: false,
+                    doh: DohConfig::default(),
+                    browser_executable: browser_executable_from_env()?,
+                    protocol: ProtocolConfig::from_env()?,
+                    fingerprint: FingerprintConfig::from_env(),
+                    extra_args: Vec::new(),
+                })
+            }
+        })
+    }
+
+    /// Применяет к странице задержку `rtt` и пропускную способность `bandwidth` устройства
+    /// (CDP `Network.emulateNetworkConditions`); загрузка и отдача ограничиваются одинаково
+    pub async fn apply_network_conditions(page: &Page, connection_info: &ConnectionInfo) -> Result<()> {
+        let throughput = connection_info.throughput_bytes_per_sec()?;
+        let mut params = EmulateNetworkConditionsParams::new(
+            connection_info.connection_type() == ConnectionType::None,
+            connection_info.rtt as f64,
+            throughput,
+            throughput,
+        );
+        params.connection_type = Some(connection_info.connection_type());
+        page.execute(params)
+            .await
+            .map_err(|e| anyhow!("Не удалось применить сетевые условия: {}", e))?;
+        Ok(())
+    }
+
+    pub fn user_agent(&self) -> &str {
+        match self {
+            EmulatedBrowser::Webkit(webkit_config) => &webkit_config.user_agent,
+            EmulatedBrowser::ChromiumBased(chrome_config) => &chrome_config.user_agent,
+        }
+    }
+
+    /// Набор флагов запуска по умолчанию для варианта браузера.
+    /// Движок в обоих случаях Chromium, но для WebKit (iOS/Safari) оставляем только
+    /// урезанный набор без Chromium/WebView-специфичных переключателей
+    pub fn default_flags(&self) -> Vec<String> {
+        let mut flags = base_chromium_args();
+
+        if let EmulatedBrowser::ChromiumBased(chrome_config) = self {
+            flags.extend(
+                [
+                    "--disable-background-timer-throttling",
+                    "--disable-backgrounding-occluded-windows",
+                    "--disable-features=AudioServiceOutOfProcess",
+                    "--disable-hang-monitor",
+                    "--disable-ipc-flooding-protection",
+                ]
+                .map(String::from),
+            );
+
+            if chrome_config.force_webview {
+                flags.push("--force-webview".to_string());
+            }
+        }
+
+        flags
+    }
+
+    /// Копия браузера с дополнительными флагами запуска (`--proxy-server=...`, `--lang=...`).
+    /// Флаг с тем же именем, что у флага по умолчанию, заменяет его; повторный вызов
+    /// добавляет флаги к уже заданным. `--disable-features`/`--enable-features` не заменяются,
+    /// а объединяются со списками по умолчанию (см. `launch_args`)
+    pub fn with_extra_args(&self, args: Vec<String>) -> Self {
+        let mut browser = self.clone();
+        let extra_args = match &mut browser {
+            EmulatedBrowser::Webkit(webkit_config) => &mut webkit_config.extra_args,
+            EmulatedBrowser::ChromiumBased(chrome_config) => &mut chrome_config.extra_args,
+        };
+        let names: Vec<&str> = args.iter().filter(|arg| !is_feature_switch(arg)).map(|arg| flag_name(arg)).collect();
+        extra_args.retain(|arg| !names.contains(&flag_name(arg)));
+        extra_args.extend(args);
+        browser
+    }
+
+    /// Копия браузера с `--proxy-server` прокси устройства; без прокси - неизмененная копия.
+    /// Учетные данные прокси подключаются к странице через `proxy::enable_proxy_auth`
+    pub fn with_proxy(&self, proxy: Option<&ProxyConfig>) -> Self {
+        match proxy {
+            Some(proxy) => self.with_extra_args(proxy.chromium_args()),
+            None => self.clone(),
+        }
+    }
+
+    pub fn extra_args(&self) -> &[String] {
+        match self {
+            EmulatedBrowser::Webkit(webkit_config) => &webkit_config.extra_args,
+            EmulatedBrowser::ChromiumBased(chrome_config) => &chrome_config.extra_args,
+        }
+    }
+
+    /// Итоговый список флагов запуска: флаги по умолчанию, DoH, транспорт и fingerprint,
+    /// затем `extra_args`, вытесняющие одноименные флаги. Списки features всех источников
+    /// объединяются в один `--disable-features` и один `--enable-features`
+    pub fn launch_args(&self) -> Vec<String> {
+        let mut args = vec![format!("--user-agent={}", self.user_agent())];
+        args.extend(self.default_flags());
+        args.extend(self.doh().chromium_args());
+        args.extend(self.protocol().chromium_args());
+        args.extend(self.fingerprint().chromium_args());
+        merge_feature_switches(with_overrides(args, self.extra_args()))
+    }
+
+    /// Флаги для `chromium.launch` Playwright: DoH и `extra_args`. Остальное (user agent,
+    /// viewport, fingerprint) Playwright задает опциями контекста и init scripts
+    pub fn playwright_args(&self) -> Vec<String> {
+        merge_feature_switches(with_overrides(self.doh().chromium_args(), self.extra_args()))
+    }
+
+    /// Копия браузера с режимом DNS-over-HTTPS `doh`
+    pub fn with_doh(&self, doh: DohConfig) -> Result<Self> {
+        doh.validate()?;
+        let mut browser = self.clone();
+        match &mut browser {
+            EmulatedBrowser::Webkit(webkit_config) => webkit_config.doh = doh,
+            EmulatedBrowser::ChromiumBased(chrome_config) => chrome_config.doh = doh,
+        }
+        Ok(browser)
+    }
+
+    pub fn browser_executable(&self) -> Option<&PathBuf> {
+        match self {
+            EmulatedBrowser::Webkit(webkit_config) => webkit_config.browser_executable.as_ref(),
+            EmulatedBrowser::ChromiumBased(chrome_config) => chrome_config.browser_executable.as_ref(),
+        }
+    }
+
+    pub fn protocol(&self) -> &ProtocolConfig {
+        match self {
+            EmulatedBrowser::Webkit(webkit_config) => &webkit_config.protocol,
+            EmulatedBrowser::ChromiumBased(chrome_config) => &chrome_config.protocol,
+        }
+    }
+
+    pub fn fingerprint(&self) -> &FingerprintConfig {
+        match self {
+            EmulatedBrowser::Webkit(webkit_config) => &webkit_config.fingerprint,
+            EmulatedBrowser::ChromiumBased(chrome_config) => &chrome_config.fingerprint,
+        }
+    }
+
+    pub fn doh(&self) -> &DohConfig {
+        match self {
+            EmulatedBrowser::Webkit(webkit_config) => &webkit_config.doh,
+            EmulatedBrowser::ChromiumBased(chrome_config) => &chrome_config.doh,
+        }
+    }
+
+    /// Конфигурация запуска: размер окна браузера берется из `metrics.window`, а viewport
+    /// страницы применяется через CDP `Emulation.setDeviceMetricsOverride`.
+    ///
+    /// Флаги для обоих вариантов: `--user-agent`, общая часть `default_flags`, DoH
+    /// (`DohConfig::chromium_args`), транспорт (`ProtocolConfig::chromium_args`) и `--use-gl=angle`
+    /// при подмене WebGL. Только Chromium устройства дополнительно получают throttling/IPC
+    /// флаги из `default_flags` и, если включено, `--force-webview`. Последними идут `extra_args`
+    /// (см. `launch_args`)
+    pub fn get_browser_config(&self, metrics: &ScreenMetrics) -> Result<BrowserConfig> {
+        self.doh().validate()?;
+
+        let viewport = Viewport {
+            width: metrics.viewport.width,
+            height: metrics.viewport.height,
+            device_scale_factor: Some(metrics.pixel_ratio as f64),
+            emulating_mobile: true,
+            is_landscape: metrics.viewport.width > metrics.viewport.height,
+            has_touch: metrics.touch_points > 0,
         };
-        Ok(config)
+
+        let mut builder = BrowserConfig::builder()
+            .window_size(metrics.window.width, metrics.window.height)
+            .viewport(viewport)
+            .args(self.launch_args());
+
+        if let Some(executable) = self.browser_executable() {
+            builder = builder.chrome_executable(executable);
+        }
+
+        builder
+            .build()
+            .map_err(|e| anyhow!(e))
     }
 }
 
+fn global_devices() -> Result<&'static Arc<RwLock<DeviceManager>>> {
+    GLOBAL_DEVICES.get()
+        .ok_or_else(|| anyhow!("Device manager not initialized"))
+}
+
+// Порядок блокировок: глобальные геттеры берут read-lock GLOBAL_DEVICES только на время
+// клонирования данных и не вызывают других функций, берущих lock. tokio::sync::RwLock
+// не реентерабелен, поэтому код, уже держащий guard (особенно write), должен использовать
+// методы DeviceManager (device_metadata/device_browser), а не эти функции — иначе deadlock.
+
+/// Идентификаторы всех устройств в отсортированном порядке.
+/// Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn list_devices() -> Result<Vec<String>> {
+    let devices = global_devices()?;
+    let manager = devices.read().await;
+    Ok(manager.device_ids())
+}
+
+/// Количество зарегистрированных устройств. Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn device_count() -> Result<usize> {
+    let devices = global_devices()?;
+    let manager = devices.read().await;
+    Ok(manager.devices.len())
+}
+
+/// Таймаут автоматизации устройства, если он переопределен. Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn get_device_automation_timeout(device_id: &str) -> Result<Option<Duration>> {
+    let devices = global_devices()?;
+    let manager = devices.read().await;
+    manager.automation_timeout(device_id)
+}
+
+/// Переопределяет таймаут автоматизации устройства (см. `DeviceManager::set_automation_timeout`)
+pub async fn set_device_automation_timeout(device_id: &str, automation_timeout: Option<Duration>) -> Result<()> {
+    let devices = global_devices()?;
+    let mut manager = devices.write().await;
+    manager.set_automation_timeout(device_id, automation_timeout)
+}
+
+/// Задает режим DNS-over-HTTPS устройства (см. `DeviceManager::set_doh`)
+pub async fn set_device_doh(device_id: &str, doh: DohConfig) -> Result<()> {
+    let devices = global_devices()?;
+    let mut manager = devices.write().await;
+    manager.set_doh(device_id, doh)
+}
+
+/// Возвращает копию метаданных устройства. Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
 pub async fn get_device_metadata(device_id: &str) -> Result<DeviceMetadata> {
-    let devices = GLOBAL_DEVICES.get()
-        .ok_or_else(|| anyhow!("Device manager not initialized"))?;
-    
+    let devices = global_devices()?;
     let manager = devices.read().await;
-    manager.devices.get(device_id)
-        .map(|device| device.metadata.clone())
-        .ok_or_else(|| anyhow!("Device not found"))
+    manager.device_metadata(device_id)
 }
 
-pub async fn get_device_browser(device_id: &str) -> Result<Arc<EmulatedBrowser>> {
-    let devices = GLOBAL_DEVICES.get()
-        .ok_or_else(|| anyhow!("Device manager not initialized"))?;
-    
+/// Возвращает копию устройства (метаданные и браузер), например для опций Playwright.
+/// Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn get_device(device_id: &str) -> Result<EmulatedDevice> {
+    let devices = global_devices()?;
     let manager = devices.read().await;
-    manager.devices.get(device_id)
-        .map(|device| Arc::new(device.browser.clone()))
-        .ok_or_else(|| anyhow!("Device not found"))
+    manager.device(device_id)
+}
+
+/// Возвращает копию браузера устройства. Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn get_device_browser(device_id: &str) -> Result<Arc<EmulatedBrowser>> {
+    let devices = global_devices()?;
+    let browser = {
+        let manager = devices.read().await;
+        manager.device_browser(device_id)?
+    };
+    Ok(Arc::new(browser))
+}
+
+/// Снимок всего парка устройств для отладки (`snapshot_emulation` / `restore_emulation`)
+#[derive(Debug, Serialize, Deserialize)]
+struct EmulationSnapshot {
+    schema_version: u32,
+    devices: HashMap<String, EmulatedDevice>,
+}
+
+/// Сохраняет состояние всех эмулируемых устройств в JSON файл.
+/// Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn snapshot_emulation(path: &Path) -> Result<()> {
+    let devices = global_devices()?;
+    let snapshot = {
+        let manager = devices.read().await;
+        EmulationSnapshot {
+            schema_version: SNAPSHOT_SCHEMA_VERSION,
+            devices: manager.devices.clone(),
+        }
+    };
+
+    fs::write(path, serde_json::to_string_pretty(&snapshot)?)
+        .map_err(|e| anyhow!("Не удалось записать снимок эмуляции {}: {}", path.display(), e))?;
+    info!("Снимок эмуляции ({} устройств) сохранен в {}", snapshot.devices.len(), path.display());
+    Ok(())
+}
+
+/// Заменяет парк устройств содержимым снимка. Снимок более новой версии схемы отклоняется.
+/// Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn restore_emulation(path: &Path) -> Result<()> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Не удалось прочитать снимок эмуляции {}: {}", path.display(), e))?;
+    let mut snapshot: EmulationSnapshot = serde_json::from_str(&content)
+        .map_err(|e| anyhow!("Ошибка разбора снимка эмуляции {}: {}", path.display(), e))?;
+
+    if snapshot.schema_version > SNAPSHOT_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "Снимок эмуляции версии {} не поддерживается (максимум {})",
+            snapshot.schema_version,
+            SNAPSHOT_SCHEMA_VERSION
+        ));
+    }
+
+    for device in snapshot.devices.values_mut() {
+        device.metadata.timezone = normalize_timezone(&device.metadata.timezone)?;
+        device.metadata.validate_context_mode()?;
+    }
+
+    let devices = global_devices()?;
+    let mut manager = devices.write().await;
+    manager.replace_devices(snapshot.devices);
+    info!("Состояние эмуляции ({} устройств) восстановлено из {}", manager.devices.len(), path.display());
+    Ok(())
+}
+
+/// Собирает конфигурацию браузера устройства по его собственным метрикам экрана и с его прокси.
+/// Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn get_device_browser_config(device_id: &str) -> Result<BrowserConfig> {
+    let devices = global_devices()?;
+    let (browser, metrics) = {
+        let manager = devices.read().await;
+        manager.touch_existing(device_id);
+        let device = manager.devices.get(device_id)
+            .ok_or_else(|| anyhow!("Device not found"))?;
+        (
+            device.browser.with_proxy(device.metadata.connection_info.proxy.as_ref()),
+            device.metadata.screen_metrics.clone(),
+        )
+    };
+    browser.get_browser_config(&metrics)
+}
+
+/// Перечитывает файл профилей (`[emulation] profiles` или DEVICE_PROFILES) и обновляет метаданные уже зарегистрированных устройств
+/// (`DeviceManager::update_metadata`) без перезапуска. Файл сначала целиком проверяется, поэтому
+/// ошибочный профиль не меняет ни одного устройства. Новые профили и устройства, пропавшие из
+/// файла, пропускаются с предупреждением: состав парка меняется только при запуске.
+/// Возвращает число обновленных устройств. Нельзя вызывать при удерживаемом guard GLOBAL_DEVICES
+pub async fn reload_device_profiles(emulation: &EmulationConfig) -> Result<usize> {
+    let path = emulation.profiles()
+        .ok_or_else(|| anyhow!("Файл профилей устройств не задан, перезагружать нечего"))?;
+    let mut loaded = DeviceManager::new();
+    loaded.load_profiles_from_path(&path)?;
+
+    let devices = global_devices()?;
+    let mut manager = devices.write().await;
+    let mut updated = 0;
+    for device_id in loaded.device_ids() {
+        if !manager.devices.contains_key(&device_id) {
+            warn!("Профиль {} не зарегистрирован в парке, пропущен до перезапуска", device_id);
+            continue;
+        }
+        manager.update_metadata(&device_id, loaded.device_metadata(&device_id)?)?;
+        updated += 1;
+    }
+    for device_id in manager.device_ids() {
+        if !loaded.devices.contains_key(&device_id) {
+            warn!("Устройство {} отсутствует в {}, оставлено без изменений", device_id, path.display());
+        }
+    }
+
+    info!("Перезагружено профилей устройств: {} из {}", updated, path.display());
+    Ok(updated)
 }
 
 // Публичный API для работы с устройствами
-pub async fn initialize_emulation() -> Result<()> {
+/// Инициализирует глобальный парк устройств по секции [emulation]. Если задан `device_filter`,
+/// создается только устройство с этим идентификатором
+pub async fn initialize_emulation(
+    device_filter: Option<&str>,
+    emulation: &EmulationConfig,
+    locale_pool: &LocalePoolConfig,
+) -> Result<()> {
 
     info!("Инициализация эмуляции устройств...");
 
-    let device_manager = Arc::new(RwLock::new(DeviceManager::new()));
+    // Файл профилей заменяет встроенные iOS/Android устройства
+    let profiles_path = emulation.profiles();
+
+    if let Some(device_id) = device_filter {
+        if profiles_path.is_none() && device_id != DEFAULT_IOS_DEVICE_ID && device_id != DEFAULT_ANDROID_DEVICE_ID {
+            return Err(anyhow!(
+                "Неизвестное устройство '{}' (доступны: {}, {})",
+                device_id,
+                DEFAULT_IOS_DEVICE_ID,
+                DEFAULT_ANDROID_DEVICE_ID
+            ));
+        }
+        info!("Инициализируется только устройство {}", device_id);
+    }
+    let wanted = |device_id: &str| device_filter.is_none_or(|filter| filter == device_id);
+
+    // Лимит ограничивает парк для долгоживущих сервисов с короткоживущими устройствами
+    let manager = match emulation.max_devices() {
+        Some(capacity) => DeviceManager::with_capacity(capacity),
+        None => DeviceManager::new(),
+    };
+    let device_manager = Arc::new(RwLock::new(manager));
     
     {
+        let ios_version = emulation.ios_version()
+            .unwrap_or_else(|| DEFAULT_IOS_VERSION.to_string());
+        let android_version = emulation.android_version()
+            .unwrap_or_else(|| DEFAULT_ANDROID_VERSION.to_string());
+        let android_density = emulation.android_density()?;
+
         let mut manager = device_manager.write().await;
-        manager.create_ios_device("ios_device").await?;
-        manager.create_android_device("android_device").await?;
+        if let Some(path) = &profiles_path {
+            manager.load_profiles_from_path(path)?;
+            for device_id in manager.device_ids() {
+                if !wanted(&device_id) {
+                    manager.remove_device(&device_id)?;
+                }
+            }
+            if manager.devices.is_empty() {
+                return Err(match device_filter {
+                    Some(device_id) => anyhow!("Устройство '{}' не найдено в профилях {}", device_id, path.display()),
+                    None => anyhow!("Файл профилей {} не содержит устройств", path.display()),
+                });
+            }
+        } else {
+            if wanted(DEFAULT_IOS_DEVICE_ID) {
+                manager.create_ios_device_with_version(DEFAULT_IOS_DEVICE_ID, &ios_version).await?;
+            }
+            if wanted(DEFAULT_ANDROID_DEVICE_ID) {
+                manager.create_android_device_with_version(DEFAULT_ANDROID_DEVICE_ID, &android_version, android_density).await?;
+            }
+        }
+
+        if let Some(mut pool) = ProxyPool::from_env()? {
+            manager.assign_proxies(&mut pool).await?;
+        }
+
+        if !locale_pool.locales.is_empty() {
+            manager.assign_locales(&locale_pool.locales, locale_pool.strategy)?;
+        }
     }
     
     GLOBAL_DEVICES.set(device_manager)
@@ -332,4 +2616,160 @@ struct ChromeFlags {
     pub enable_automation: bool,
     pub disable_web_security: bool,
     pub ignore_certificate_errors: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn android_device() -> EmulatedDevice {
+        EmulatedDeviceBuilder::new("test-android").build().unwrap()
+    }
+
+    #[test]
+    fn save_data_reaches_context_options_and_init_scripts() {
+        let mut device = android_device();
+        device.metadata.connection_info.save_data = true;
+
+        let options = device.to_playwright_context_options();
+        assert_eq!(options["extra_http_headers"]["Save-Data"], "on");
+        let scripts = device.init_scripts().unwrap();
+        assert!(scripts.iter().any(|script| script.contains("saveData")));
+    }
+
+    #[test]
+    fn save_data_off_adds_nothing() {
+        let device = android_device();
+
+        let options = device.to_playwright_context_options();
+        assert!(options["extra_http_headers"].get("Save-Data").is_none());
+        let scripts = device.init_scripts().unwrap();
+        assert!(!scripts.iter().any(|script| script.contains("saveData")));
+    }
+
+    #[test]
+    fn denied_and_prompt_permissions_are_exported_as_overrides() {
+        let mut device = android_device();
+        device.metadata.permissions.insert("camera".to_string(), PermissionState::Prompt);
+        device.metadata.permissions.insert("clipboard-read".to_string(), PermissionState::Granted);
+
+        assert_eq!(device.granted_permissions(), vec!["clipboard-read".to_string()]);
+        let overrides = device.permission_overrides();
+        assert_eq!(overrides.get("notifications"), Some(&PermissionState::Denied));
+        assert_eq!(overrides.get("camera"), Some(&PermissionState::Prompt));
+        assert!(!overrides.contains_key("clipboard-read"));
+        assert_eq!(json!(overrides)["notifications"], "denied");
+    }
+
+    #[test]
+    fn extra_headers_reach_context_options_without_managed_headers() {
+        let mut device = android_device();
+        device.metadata.extra_headers.insert("X-Requested-With".to_string(), "org.telegram.messenger".to_string());
+        device.metadata.extra_headers.insert("Host".to_string(), "example.com".to_string());
+
+        let options = device.to_playwright_context_options();
+        let headers = options["extra_http_headers"].as_object().unwrap();
+        assert_eq!(headers["X-Requested-With"], "org.telegram.messenger");
+        assert!(!headers.contains_key("Host"));
+    }
+
+    #[test]
+    fn feature_switches_are_merged_into_one_per_kind() {
+        let device = android_device();
+        let browser = device
+            .browser
+            .with_doh(DohConfig::Off)
+            .unwrap()
+            .with_extra_args(vec!["--disable-features=Translate".to_string(), "--enable-features=NetworkService".to_string()]);
+
+        let args = browser.launch_args();
+        let disabled: Vec<&String> = args.iter().filter(|arg| arg.starts_with("--disable-features=")).collect();
+        assert_eq!(disabled, ["--disable-features=AudioServiceOutOfProcess,DnsOverHttps,Translate"]);
+        assert_eq!(args.iter().filter(|arg| arg.starts_with("--enable-features=")).count(), 1);
+    }
+
+    #[test]
+    fn doh_reaches_playwright_launch_args() {
+        let mut device = android_device();
+        device.browser = device.browser.with_doh(DohConfig::Automatic).unwrap();
+
+        let options = device.to_playwright_launch_options();
+        assert_eq!(options["args"], json!(["--enable-features=DnsOverHttps"]));
+        assert!(device.browser.with_doh(DohConfig::Secure { template: "http://dns.example".to_string() }).is_err());
+    }
+
+    #[tokio::test]
+    async fn randomized_android_hardware_model_matches_user_agent() {
+        let mut manager = DeviceManager::new();
+        for seed in 0..64 {
+            let id = format!("android-{}", seed);
+            manager.create_randomized_android_device(&id, Some(seed)).await.unwrap();
+            let metadata = manager.device_metadata(&id).unwrap();
+
+            let hints = ClientHints::from_user_agent(&metadata.user_agent).unwrap();
+            assert_eq!(hints.model, metadata.hardware_info.model, "seed {}", seed);
+            let model = ANDROID_MODELS.iter().find(|m| m.ua_model == hints.model).unwrap();
+            assert!(model.os_versions.contains(&hints.platform_version.as_str()), "seed {}", seed);
+        }
+    }
+
+    #[tokio::test]
+    async fn randomized_ios_version_is_supported_by_model() {
+        let mut manager = DeviceManager::new();
+        for seed in 0..64 {
+            let id = format!("ios-{}", seed);
+            manager.create_randomized_ios_device(&id, Some(seed)).await.unwrap();
+            let metadata = manager.device_metadata(&id).unwrap();
+
+            let hints = ClientHints::from_user_agent(&metadata.user_agent).unwrap();
+            let model = IOS_MODELS.iter().find(|m| m.name == metadata.hardware_info.model).unwrap();
+            assert!(model.os_versions.contains(&hints.platform_version.as_str()), "seed {}", seed);
+        }
+    }
+
+    #[tokio::test]
+    async fn readers_share_lock_while_writer_waits() {
+        let mut manager = DeviceManager::new();
+        manager.create_ios_device_with_version("shared", "17.4").await.unwrap();
+        let devices = Arc::new(RwLock::new(manager));
+
+        // Все читатели держат read-lock одновременно, пока писатель ждет
+        let readers_ready = Arc::new(tokio::sync::Barrier::new(4));
+        let (release_tx, _) = tokio::sync::broadcast::channel::<()>(1);
+        let mut readers = Vec::new();
+        for _ in 0..3 {
+            let devices = devices.clone();
+            let ready = readers_ready.clone();
+            let mut release = release_tx.subscribe();
+            readers.push(tokio::spawn(async move {
+                let manager = devices.read().await;
+                ready.wait().await;
+                let _ = release.recv().await;
+                manager.device_metadata("shared").map(|m| m.device_id)
+            }));
+        }
+        readers_ready.wait().await;
+
+        let writer = tokio::spawn({
+            let devices = devices.clone();
+            async move {
+                let mut manager = devices.write().await;
+                manager.set_automation_timeout("shared", Some(Duration::from_secs(5)))
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!writer.is_finished(), "писатель не должен получить lock, пока его держат читатели");
+
+        release_tx.send(()).unwrap();
+        let all = async {
+            for reader in readers {
+                assert_eq!(reader.await.unwrap().unwrap(), "shared");
+            }
+            writer.await.unwrap().unwrap();
+        };
+        tokio::time::timeout(Duration::from_secs(5), all).await.expect("deadlock на GLOBAL_DEVICES");
+
+        let manager = devices.read().await;
+        assert_eq!(manager.automation_timeout("shared").unwrap(), Some(Duration::from_secs(5)));
+    }
 }
\ No newline at end of file
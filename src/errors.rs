@@ -1,7 +1,42 @@
 use thiserror::Error;
 
+/// Ошибки graceful shutdown. Варианты различаются, чтобы main мог решить, какие из них
+/// фатальны (exit 1), а какие достаточно записать в лог
 #[derive(Debug, Error)]
 pub enum ShutdownError {
-    #[error("Platform error: {0}")]
-    PlatformError(#[from] anyhow::Error),
-}
\ No newline at end of file
+    /// Не удалось отправить сигнал процессу
+    #[error("Failed to send {signal} to process {pid}: {reason}")]
+    SignalError { pid: i32, signal: &'static str, reason: String },
+    /// Не удалось получить, использовать или закрыть дескриптор процесса
+    #[error("Process {pid} handle error: {reason}")]
+    ProcessHandleError { pid: i32, reason: String },
+    /// Шаг очистки не уложился в свой таймаут
+    #[error("Cleanup step '{step}' timed out after {timeout:?}")]
+    CleanupTimeout { step: &'static str, timeout: std::time::Duration },
+    /// Очистка Python ресурсов (gc) завершилась исключением
+    #[error("Python cleanup error: {0}")]
+    PythonCleanupError(String),
+    /// Прочий шаг очистки (heartbeat файл, кэш Playwright) завершился ошибкой
+    #[error("Cleanup step '{step}' failed: {reason}")]
+    CleanupStepError { step: &'static str, reason: String },
+    /// Ошибки шагов очистки; остальные шаги при этом выполнены
+    #[error("Cleanup failed: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    CleanupFailed(Vec<ShutdownError>),
+}
+
+/// Предварительная проверка (AUTOMATION_PRECHECK) не пройдена: окружение не готово,
+/// основная автоматизация не запускалась
+#[derive(Debug, Error)]
+#[error("Precheck {precheck} failed: {reason}")]
+pub struct PrecheckFailed {
+    pub precheck: String,
+    pub reason: String,
+}
+
+/// Бот не дошел до состояния Running за отведенное время
+#[derive(Debug, Error)]
+#[error("Bot not ready after {timeout:?} (reached {reached:?})")]
+pub struct ReadinessTimeout {
+    pub timeout: std::time::Duration,
+    pub reached: crate::config::Readiness,
+}
@@ -0,0 +1,195 @@
+use anyhow::{Result, anyhow};
+use log::{info, error, warn};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+use crate::emulation::get_device_metadata;
+use crate::utils::env_flag;
+
+// Количество последних строк лога в архиве по умолчанию
+const DEFAULT_LOG_LINES: usize = 200;
+
+// Сколько последних архивов хранить по умолчанию
+const DEFAULT_RETENTION: usize = 20;
+
+/// Собирает отладочный архив `logs/failure-<timestamp>.zip` при ошибке автоматизации:
+/// текст ошибки, последние строки лога, метаданные устройства и последние скриншот, HAR и трейс.
+/// Артефакты ищутся только в `output_dir` устройства (`RunContext::output_dir`, без него -
+/// `./recordings`), хвост лога - в `log_file` (`logging::log_file`, без записи в файл его нет).
+/// Включается через COLLECT_FAILURE_BUNDLE=true, иначе возвращает `None`
+pub async fn collect_failure_bundle(
+    device_id: Option<&str>,
+    output_dir: Option<&Path>,
+    log_file: Option<&Path>,
+    failure: &anyhow::Error,
+) -> Result<Option<PathBuf>> {
+    if !env_flag("COLLECT_FAILURE_BUNDLE", false) {
+        return Ok(None);
+    }
+
+    let current_dir = std::env::current_dir()?;
+    let logs_dir = current_dir.join("logs");
+    let recordings_dir = match output_dir {
+        Some(output_dir) => output_dir.to_path_buf(),
+        None => current_dir.join("recordings"),
+    };
+    fs::create_dir_all(&logs_dir)?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let bundle_path = logs_dir.join(format!("failure-{}.zip", timestamp));
+
+    // Метаданные устройства берем до синхронной записи архива
+    let device_metadata = match device_id {
+        Some(device_id) => match get_device_metadata(device_id).await {
+            Ok(metadata) => Some(serde_json::to_string_pretty(&metadata)?),
+            Err(e) => {
+                warn!("Метаданные устройства {} недоступны для архива: {}", device_id, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut zip = ZipWriter::new(File::create(&bundle_path)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("error.txt", options)?;
+    zip.write_all(format!("{:#}\n", failure).as_bytes())?;
+
+    if let Some(metadata) = device_metadata {
+        zip.start_file("device_metadata.json", options)?;
+        zip.write_all(metadata.as_bytes())?;
+    }
+
+    if let Some(log_file) = log_file.filter(|path| path.is_file()) {
+        let lines = std::env::var("FAILURE_BUNDLE_LOG_LINES")
+            .ok()
+            .and_then(|value| value.trim().parse::<usize>().ok())
+            .unwrap_or(DEFAULT_LOG_LINES);
+        zip.start_file("log_tail.txt", options)?;
+        zip.write_all(tail_lines(log_file, lines)?.as_bytes())?;
+    }
+
+    if let Some(screenshot) = latest_file(&recordings_dir, |path| has_extension(path, &["png", "jpg", "jpeg"]))? {
+        add_file(&mut zip, &screenshot, "screenshot", options)?;
+    }
+
+    if let Some(har) = latest_file(&recordings_dir, |path| has_extension(path, &["har"]))? {
+        add_file(&mut zip, &har, "recording", options)?;
+    }
+
+    // Трейс TracerManager: <output_dir>/tracer/trace_<timestamp>/interactions.json
+    let is_trace = |path: &Path| path.file_name().is_some_and(|name| name == "interactions.json");
+    if let Some(trace) = latest_file(&recordings_dir.join("tracer"), is_trace)? {
+        add_file(&mut zip, &trace, "trace", options)?;
+    }
+
+    zip.finish()?;
+    info!("Отладочный архив ошибки сохранен: {}", bundle_path.display());
+
+    let retention = std::env::var("FAILURE_BUNDLE_RETENTION")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_RETENTION);
+    if let Err(e) = prune_bundles(&logs_dir, retention) {
+        warn!("Не удалось удалить старые архивы ошибок: {}", e);
+    }
+
+    Ok(Some(bundle_path))
+}
+
+/// Вызывается из пути ошибки: сбой сборки архива не должен маскировать исходную ошибку
+pub async fn collect_failure_bundle_logged(
+    device_id: Option<&str>,
+    output_dir: Option<&Path>,
+    log_file: Option<&Path>,
+    failure: &anyhow::Error,
+) {
+    if let Err(e) = collect_failure_bundle(device_id, output_dir, log_file, failure).await {
+        error!("Не удалось собрать архив ошибки: {}", e);
+    }
+}
+
+/// Оставляет в `logs_dir` только `keep` самых свежих (по mtime) архивов `failure-*.zip`.
+/// `keep == 0` отключает очистку
+pub fn prune_bundles(logs_dir: &Path, keep: usize) -> Result<()> {
+    if keep == 0 {
+        return Ok(());
+    }
+
+    let mut bundles: Vec<(SystemTime, PathBuf)> = Vec::new();
+    for entry in fs::read_dir(logs_dir)? {
+        let path = entry?.path();
+        if is_bundle_file_name(&path) && path.is_file() {
+            bundles.push((fs::metadata(&path)?.modified()?, path));
+        }
+    }
+
+    // Самые свежие в начале
+    bundles.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in bundles.into_iter().skip(keep) {
+        info!("Удаление старого архива ошибки: {}", path.display());
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+/// Имя архива ошибки `failure-*.zip`: их количеством управляет `prune_bundles`
+pub(crate) fn is_bundle_file_name(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.starts_with("failure-") && name.ends_with(".zip"))
+}
+
+fn add_file(zip: &mut ZipWriter<File>, path: &Path, prefix: &str, options: FileOptions) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Некорректный путь: {}", path.display()))?
+        .to_string_lossy();
+    zip.start_file(format!("{}/{}", prefix, file_name), options)?;
+    zip.write_all(&fs::read(path)?)?;
+    Ok(())
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|ext| extensions.iter().any(|wanted| ext.eq_ignore_ascii_case(wanted)))
+        .unwrap_or(false)
+}
+
+/// Рекурсивно ищет самый свежий (по mtime) файл, подходящий под фильтр
+fn latest_file(dir: &Path, filter: impl Fn(&Path) -> bool + Copy) -> Result<Option<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+
+    let mut latest: Option<(SystemTime, PathBuf)> = None;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let candidate = if path.is_dir() {
+            latest_file(&path, filter)?
+        } else if filter(&path) {
+            Some(path)
+        } else {
+            None
+        };
+
+        if let Some(candidate) = candidate {
+            let modified = fs::metadata(&candidate)?.modified()?;
+            if latest.as_ref().is_none_or(|(time, _)| modified > *time) {
+                latest = Some((modified, candidate));
+            }
+        }
+    }
+    Ok(latest.map(|(_, path)| path))
+}
+
+fn tail_lines(path: &Path, count: usize) -> Result<String> {
+    let content = String::from_utf8_lossy(&fs::read(path)?).into_owned();
+    let lines: Vec<&str> = content.lines().collect();
+    let start = lines.len().saturating_sub(count);
+    Ok(lines[start..].join("\n"))
+}
@@ -0,0 +1,132 @@
+//! HTTP сервер состояния для деплоя.
+//!
+//! Доступен только с фичей `health-server`, адрес задается через HEALTH_ADDR. Маршруты:
+//! - `/healthz` — JSON с `ShutdownState` и этапом запуска; 200 пока бот работает, 503 после
+//!   начала остановки
+//! - `/metrics` — счетчики автоматизации и число эмулируемых устройств в текстовом формате Prometheus
+//!
+//! Сервер останавливается вместе с процессом по `SystemConfig::request_shutdown`
+
+use anyhow::{Result, anyhow};
+use log::{debug, error, info};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use crate::config::{ShutdownState, SystemConfig};
+use crate::emulation;
+
+// Адрес сервера состояния по умолчанию, переопределяется через HEALTH_ADDR
+const DEFAULT_HEALTH_ADDR: &str = "127.0.0.1:9090";
+
+/// Адрес сервера состояния из HEALTH_ADDR
+pub fn health_addr() -> String {
+    std::env::var("HEALTH_ADDR")
+        .ok()
+        .map(|addr| addr.trim().to_string())
+        .filter(|addr| !addr.is_empty())
+        .unwrap_or_else(|| DEFAULT_HEALTH_ADDR.to_string())
+}
+
+/// Запускает сервер состояния на `addr`. Цикл accept завершается после `request_shutdown`
+pub async fn start(system: Arc<SystemConfig>, addr: &str) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| anyhow!("Не удалось запустить сервер состояния на {}: {}", addr, e))?;
+    info!("Сервер состояния запущен на {} (/healthz, /metrics)", listener.local_addr()?);
+
+    Ok(tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = system.wait_shutdown() => {
+                    info!("Сервер состояния остановлен");
+                    break;
+                },
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        let system = Arc::clone(&system);
+                        tokio::spawn(async move {
+                            if let Err(e) = handle_connection(stream, &system).await {
+                                debug!("Ошибка обработки запроса состояния от {}: {}", peer, e);
+                            }
+                        });
+                    },
+                    Err(e) => {
+                        error!("Ошибка accept сервера состояния: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }))
+}
+
+async fn handle_connection(stream: TcpStream, system: &SystemConfig) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| anyhow!("Некорректная строка запроса: {:?}", request_line))?
+        .to_string();
+
+    // Заголовки не нужны, но их нужно дочитать до пустой строки
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let (status, content_type, body) = match path.as_str() {
+        "/healthz" => {
+            let shutdown_state = system.shutdown_state();
+            let body = json!({
+                "status": if shutdown_state == ShutdownState::Running { "ok" } else { "shutting_down" },
+                "shutdown_state": format!("{:?}", shutdown_state),
+                "readiness": format!("{:?}", system.readiness()),
+            });
+            let status = if shutdown_state == ShutdownState::Running {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            };
+            (status, "application/json", body.to_string())
+        },
+        "/metrics" => ("200 OK", "text/plain; version=0.0.4", metrics(system).await),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "not found\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+
+    let mut stream = reader.into_inner();
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Метрики в текстовом формате Prometheus. До инициализации эмуляции устройств 0
+async fn metrics(system: &SystemConfig) -> String {
+    let counters = system.automation_counters();
+    let devices = emulation::device_count().await.unwrap_or(0);
+    format!(
+        "# TYPE bombie_automation_runs_total counter\n\
+         bombie_automation_runs_total {}\n\
+         # TYPE bombie_automation_failures_total counter\n\
+         bombie_automation_failures_total {}\n\
+         # TYPE bombie_emulated_devices gauge\n\
+         bombie_emulated_devices {}\n",
+        counters.runs,
+        counters.failures,
+        devices
+    )
+}
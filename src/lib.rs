@@ -3,4 +3,14 @@ pub mod utils;
 pub mod py_automation;
 pub mod config;
 pub mod platform_specific;
-pub mod errors;
\ No newline at end of file
+pub mod errors;
+pub mod emulation;
+pub mod cli;
+pub mod failure_bundle;
+pub mod logging;
+pub mod proxy;
+pub mod run_context;
+#[cfg(feature = "health-server")]
+pub mod health;
+#[cfg(feature = "test-server")]
+pub mod test_support;
\ No newline at end of file
@@ -0,0 +1,158 @@
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use env_logger::{Builder, Env, Target};
+use serde_json::json;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use once_cell::sync::OnceCell;
+use crate::config::{LogFormat, LoggingConfig};
+
+// Имя активного файла лога, ротированные копии получают суффиксы .1, .2, ...
+pub(crate) const LOG_FILE_NAME: &str = "bombie-bot.log";
+
+// Активный файл лога, если включена запись в файл ([logging] file)
+static LOG_FILE: OnceCell<PathBuf> = OnceCell::new();
+
+/// Инициализирует логгер: stderr и/или файл с ротацией по размеру согласно секции [logging].
+/// Уровни, в том числе по модулям (`RUST_LOG=info,bombie_bot::emulation=debug`), задаются через RUST_LOG.
+/// При формате json каждая запись - одна строка JSON с полями timestamp, level, module, message
+pub fn init_logging(config: &LoggingConfig) -> Result<()> {
+    let mut builder = Builder::from_env(Env::default());
+    if config.format()? == LogFormat::Json {
+        builder.format(|buf, record| {
+            let entry = json!({
+                "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                "level": record.level().as_str(),
+                "module": record.module_path().unwrap_or_else(|| record.target()),
+                "message": record.args().to_string(),
+            });
+            writeln!(buf, "{}", entry)
+        });
+    }
+
+    if config.file {
+        fs::create_dir_all(&config.dir)?;
+        let _ = LOG_FILE.set(config.dir.join(LOG_FILE_NAME));
+        let file = RotatingFile::open(
+            config.dir.join(LOG_FILE_NAME),
+            config.max_size_mb.saturating_mul(1024 * 1024),
+            config.max_files,
+        )?;
+        builder.target(Target::Pipe(Box::new(LogTarget {
+            file: Some(file),
+            stderr: config.stderr,
+        })));
+    } else if !config.stderr {
+        builder.target(Target::Pipe(Box::new(io::sink())));
+    }
+
+    builder.try_init()?;
+    Ok(())
+}
+
+/// Активный файл лога `<[logging] dir>/bombie-bot.log`; `None`, если запись в файл выключена
+pub fn log_file() -> Option<&'static Path> {
+    LOG_FILE.get().map(PathBuf::as_path)
+}
+
+/// Дублирует записи лога в файл и (опционально) в stderr
+struct LogTarget {
+    file: Option<RotatingFile>,
+    stderr: bool,
+}
+
+impl Write for LogTarget {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+        }
+        if self.stderr {
+            io::stderr().write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+        if self.stderr {
+            io::stderr().flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Файл лога с ротацией по размеру: при превышении `max_bytes` текущий файл становится `.1`,
+/// старые копии сдвигаются, копии старше `max_files` удаляются
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            file,
+            written,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            remove_if_exists(&self.rotated_path(self.max_files))?;
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_bytes > 0 && self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -1,114 +1,416 @@
-mod py_modules;
-mod utils;
-mod py_automation;
-mod platform_specific;
-mod config;
-mod errors;
-
 use std::fs;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use anyhow::Result;
 use dotenv::dotenv;
-use log::{error, info};
+use log::{error, info, warn};
+#[cfg(windows)]
 use tokio::signal::ctrl_c;
 
-use config::{SystemConfig, ShutdownState};
-use errors::ShutdownError;
-
-#[allow(unused_imports)]
-use pyo3::Python;
+use bombie_bot::{emulation, logging, platform_specific, py_automation, utils};
+use bombie_bot::cli::Cli;
+use bombie_bot::run_context::RunContext;
+use clap::Parser;
+use bombie_bot::config::{AppConfig, CleanupConfig, Readiness, SystemConfig, ShutdownOrder};
+use bombie_bot::errors::ShutdownError;
+use bombie_bot::py_automation::{AutomationKwargs, FleetMode};
+use serde_json::json;
 
 #[allow(unused_imports)]
 use anyhow::anyhow;
 
 #[allow(unused_imports)]
-use crate::py_modules::py_setup::PythonSetup;
+use bombie_bot::py_modules::{py_imports, py_setup::PythonSetup};
 
 #[allow(unused_imports)]
-use crate::utils::{try_import_package, parse_requirements};
+use bombie_bot::utils::{parse_requirements, run_python};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::init();
-    info!("Запуск WebApp Analyzer...");
+    let cli = Cli::parse();
+    if cli.version {
+        print!("{}", PythonSetup::new()?.version_report());
+        return Ok(());
+    }
+    let run_args = cli.run_args();
 
     dotenv().ok();
+    let (app_config, config_source) = AppConfig::load()?;
+    let app_config = Arc::new(app_config);
 
-    // Удаление логов при необходимости
-    if let Err(e) = utils::delete_logs() {
-        error!("Ошибка при удалении логов: {}", e);
+    // Удаление или ротация логов выполняется до открытия файла лога с ротацией;
+    // delete_on_start важнее ротации - для запуска с чистого листа
+    let logging_config = &app_config.logging;
+    let delete_logs_result = if logging_config.rotate_on_start() && !logging_config.delete_on_start() {
+        utils::rotate_logs(&logging_config.delete_dirs(), logging_config.max_age_days(), logging_config.max_total_bytes()).map(|_| Vec::new())
+    } else {
+        utils::delete_logs(logging_config.delete_on_start(), &logging_config.delete_dirs())
+    };
+
+    logging::init_logging(&app_config.logging)?;
+    info!("Запуск WebApp Analyzer...");
+    info!("Конфигурация: {}", config_source);
+
+    match delete_logs_result {
+        Ok(deleted) => {
+            for path in deleted {
+                info!("Удалена директория: {}", path.display());
+            }
+        }
+        Err(e) => error!("Ошибка при удалении или ротации логов: {}", e),
+    }
+
+    // Пользовательские имена импорта нужны до любой проверки пакетов (в том числе doctor)
+    py_imports::load_import_overrides()?;
+
+    if cli.is_doctor() {
+        let python_setup = PythonSetup::from_config(&app_config.python)?;
+        python_setup.ensure_environment()?;
+        print!("{}", python_setup.doctor_report()?);
+        return Ok(());
     }
 
     let config = Arc::new(SystemConfig::new());
     let pid = std::process::id() as i32;
+    let shutdown_order = ShutdownOrder::from_env()?;
 
-    // Обработчик Ctrl+C
-    let config_clone = Arc::clone(&config);
-    tokio::spawn(async move {
-        if let Ok(()) = ctrl_c().await {
-            info!("Initiating graceful shutdown...");
-            
-            let result = async {
-                #[cfg(unix)]
-                platform_specific::unix::handle_shutdown(&config_clone, pid).await?;
-                
-                #[cfg(windows)]
-                platform_specific::windows::handle_shutdown(&config_clone, pid).await?;
-                
-                platform_specific::cleanup::cleanup_resources(&config_clone).await?;
-                config_clone.set_shutdown_state(ShutdownState::Completed);
-                Ok::<(), ShutdownError>(())
-            }.await;
-
-            if let Err(e) = result {
-                error!("Critical shutdown error: {}", e);
-                std::process::exit(1);
+    // Unix: SIGINT/SIGTERM - graceful shutdown, SIGHUP - перезагрузка профилей устройств.
+    // Windows: Ctrl+C - graceful shutdown
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigint = signal(SignalKind::interrupt())?;
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sighup = signal(SignalKind::hangup())?;
+        let config_clone = Arc::clone(&config);
+        let app_config = Arc::clone(&app_config);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = sigint.recv() => break,
+                    _ = sigterm.recv() => break,
+                    _ = sighup.recv() => {
+                        info!("SIGHUP received, reloading device profiles");
+                        if let Err(e) = emulation::reload_device_profiles(&app_config.emulation).await {
+                            error!("Device profiles reload error: {}", e);
+                        }
+                    }
+                }
             }
-        }
-    });
+            let second_signal = async {
+                tokio::select! {
+                    _ = sigint.recv() => {}
+                    _ = sigterm.recv() => {}
+                }
+            };
+            graceful_shutdown(&config_clone, &app_config.cleanup, pid, shutdown_order, second_signal).await;
+        });
+    }
+
+    #[cfg(windows)]
+    {
+        let config_clone = Arc::clone(&config);
+        let app_config = Arc::clone(&app_config);
+        tokio::spawn(async move {
+            if let Ok(()) = ctrl_c().await {
+                let second_signal = async {
+                    let _ = ctrl_c().await;
+                };
+                graceful_shutdown(&config_clone, &app_config.cleanup, pid, shutdown_order, second_signal).await;
+            }
+        });
+    }
+
+    // Сервер состояния останавливается сам по request_shutdown из graceful shutdown
+    #[cfg(feature = "health-server")]
+    bombie_bot::health::start(Arc::clone(&config), &bombie_bot::health::health_addr()).await?;
 
     // Инициализируем Python окружение
-    let python_setup = PythonSetup::new()?;
+    let python_setup = PythonSetup::from_config(&app_config.python)?;
+
+    // Режим проверки (health-check для CI/деплоя): только проверки, без создания venv и кэша
+    // Playwright, установки пакетов и браузеров и без автоматизации
+    if run_args.verify_only || utils::env_flag("VERIFY_ONLY", false) {
+        let started = Instant::now();
+        let verified = match python_setup.check_environment() {
+            Ok(()) => verify_environment(&python_setup, false).await,
+            Err(e) => Err(e),
+        };
+        return match verified {
+            Ok(packages) => {
+                println!("Окружение в порядке: пакетов проверено {} за {:?}", packages, started.elapsed());
+                Ok(())
+            }
+            Err(e) => {
+                println!("Проверка окружения не пройдена:\n{:#}", e);
+                Err(e)
+            }
+        };
+    }
+
+    // Создает кэш Playwright и выставляет PLAYWRIGHT_BROWSERS_PATH для Python кода
     python_setup.ensure_environment()?;
 
-    // Создаем директорию для кэша Playwright и проверяем установку
-    let playwright_cache = std::env::current_dir()?.join("target").join("playwright-cache");
-    if !playwright_cache.exists() {
-        fs::create_dir_all(&playwright_cache)?;
-    }
-
-    // Проверяем установку Playwright в виртуальном окружении
-    Python::with_gil(|py| {
-        let sys = py.import("sys")?;
-        let paths: Vec<String> = sys.getattr("path")?.extract()?;
-        info!("Python paths: {:?}", paths);
-        
-        if let Err(e) = py.import("playwright") {
-            error!("Ошибка импорта playwright: {}", e);
-            return Err(anyhow!("Playwright не установлен корректно"));
+    // Режим подготовки (например для кэширующего CI job): окружение собрано, автоматизация не нужна
+    if run_args.prepare || utils::env_flag("PREPARE_ONLY", false) {
+        info!("Окружение подготовлено, выход без запуска автоматизации (режим prepare)");
+        return Ok(());
+    }
+
+    verify_environment(&python_setup, true).await?;
+    config.set_readiness(Readiness::EnvironmentReady);
+
+    // Инициализация эмулируемых устройств (только выбранного, если указан --device)
+    emulation::initialize_emulation(run_args.device.as_deref(), &app_config.emulation, &app_config.locale_pool).await?;
+    for (device_id, device_config) in &app_config.devices {
+        // Устройства, отфильтрованные через --device, не инициализированы
+        if !emulation::list_devices().await?.contains(device_id) {
+            warn!("Секция [devices.{}]: устройство не инициализировано, параметры пропущены", device_id);
+            continue;
+        }
+        if let Some(secs) = device_config.automation_timeout_secs {
+            emulation::set_device_automation_timeout(device_id, Some(Duration::from_secs(secs))).await?;
+            info!("Таймаут автоматизации устройства {}: {}с", device_id, secs);
+        }
+        if let Some(doh) = &device_config.doh {
+            emulation::set_device_doh(device_id, doh.clone()).await?;
+            info!("DNS-over-HTTPS устройства {}: {:?}", device_id, doh);
         }
-        Ok(())
-    })?;
-
-    // Проверяем все необходимые Python импорты
-    let required_packages = parse_requirements()?;
-    Python::with_gil(|py| {
-        for package in &required_packages {
-            if let Err(e) = try_import_package(py, package) {
-                error!("Ошибка импорта пакета {}: {}", package, e);
-                return Err(anyhow!("Ошибка импорта: {}", e));
+    }
+    let automation_timeout = app_config.run.automation_timeout();
+    match automation_timeout {
+        Some(automation_timeout) => info!("Таймаут автоматизации: {:?}", automation_timeout),
+        None => info!("Таймаут автоматизации отключен (automation_timeout_secs=0)"),
+    }
+    config.set_readiness(Readiness::EmulationReady);
+
+    // Уникальные выходные директории запуска и устройств, чтобы параллельные запуски не затирали записи
+    let run_context = RunContext::from_env(&emulation::list_devices().await?)?;
+
+    // SIGUSR1 сохраняет снимок состояния эмуляции в logs/emulation-snapshot.json
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigusr1 = match signal(SignalKind::user_defined1()) {
+            Ok(sigusr1) => sigusr1,
+            Err(e) => {
+                error!("Не удалось подписаться на SIGUSR1: {}", e);
+                return;
+            }
+        };
+        while sigusr1.recv().await.is_some() {
+            let path = std::path::Path::new("logs").join("emulation-snapshot.json");
+            if let Err(e) = fs::create_dir_all("logs") {
+                error!("Ошибка создания директории логов: {}", e);
+                continue;
+            }
+            if let Err(e) = emulation::snapshot_emulation(&path).await {
+                error!("Ошибка снимка эмуляции: {}", e);
             }
         }
-        Ok(())
-    })?;
+    });
+
+    // Параметры для Python точки входа
+    let mut automation_kwargs = AutomationKwargs::new();
+    automation_kwargs.insert("automation_config".to_string(), app_config.automation.clone());
+    automation_kwargs.insert("tab_concurrency".to_string(), json!(app_config.tab_concurrency()?));
+    if let Some(session) = app_config.telegram.selected()? {
+        info!("Telegram сессия: {} ({})", session.name, session.session_file.display());
+        automation_kwargs.insert("telegram_session".to_string(), serde_json::to_value(&session)?);
+    }
+    if let Some(heartbeat_file) = utils::prepare_heartbeat_file()? {
+        automation_kwargs.insert("heartbeat_file".to_string(), json!(heartbeat_file.to_string_lossy()));
+        automation_kwargs.insert("heartbeat_interval".to_string(), json!(utils::heartbeat_interval_secs()));
+    }
 
     // Запуск автоматизации
     info!("Запуск автоматизации...");
-    if let Err(e) = py_automation::run_automation().await {
+    config.set_readiness(Readiness::Running);
+    let automation_result = if run_args.fleet {
+        let mode = match run_args.fleet_mode {
+            Some(mode) => mode,
+            None => FleetMode::from_env()?,
+        };
+        if utils::env_flag("KEEP_BROWSER_OPEN_ON_ERROR", false) {
+            warn!("KEEP_BROWSER_OPEN_ON_ERROR игнорируется в режиме парка: устройства читали бы stdin одновременно");
+        }
+        let mut fleet = Vec::new();
+        for device_id in emulation::list_devices().await? {
+            let mut device_kwargs = automation_kwargs.clone();
+            insert_device_kwargs(&mut device_kwargs, Some(&device_id), &run_context).await?;
+            fleet.push((device_id, device_kwargs));
+        }
+
+        let results = py_automation::run_fleet(
+            fleet,
+            mode,
+            app_config.success.as_ref(),
+            py_automation::automation_attempts(),
+            automation_timeout,
+            &config,
+        ).await;
+        let failed: Vec<String> = results
+            .into_iter()
+            .filter_map(|(device_id, outcome)| outcome.into_result().err().map(|e| format!("{}: {}", device_id, e)))
+            .collect();
+        if failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("Устройства завершились ошибкой:\n{}", failed.join("\n")))
+        }
+    } else {
+        insert_device_kwargs(&mut automation_kwargs, run_args.device.as_deref(), &run_context).await?;
+        let device_timeout = py_automation::automation_timeout(run_args.device.as_deref(), automation_timeout).await;
+        automation_kwargs.insert(
+            "keep_browser_open_on_error".to_string(),
+            json!(keep_browser_open_on_error(device_timeout)),
+        );
+        py_automation::run_automation_with_retry(
+            &automation_kwargs,
+            app_config.success.as_ref(),
+            py_automation::automation_attempts(),
+            automation_timeout,
+            &config,
+        ).await.into_result()
+    };
+
+    if let Err(e) = utils::remove_heartbeat_file() {
+        error!("Ошибка удаления heartbeat файла: {}", e);
+    }
+
+    if let Err(e) = automation_result {
         error!("Ошибка автоматизации: {}", e);
         return Err(e);
     }
 
     Ok(())
 
+}
+
+/// Проверяет установку Playwright и импорт всех зависимостей из requirements.
+/// С `recover` неимпортируемые пакеты переустанавливаются, без него - только ошибка.
+/// Возвращает количество проверенных пакетов
+async fn verify_environment(python_setup: &PythonSetup, recover: bool) -> Result<usize> {
+    let paths: Vec<String> = run_python(|py| py.import("sys")?.getattr("path")?.extract()).await?;
+    info!("Python paths: {:?}", paths);
+
+    if let Err(e) = run_python(|py| py.import("playwright").map(|_| ())).await {
+        error!("Ошибка импорта playwright: {}", e);
+        return Err(anyhow!("Playwright не установлен корректно"));
+    }
+
+    let required_packages: Vec<String> = parse_requirements()?
+        .into_iter()
+        .filter(|requirement| requirement.is_importable())
+        .map(|requirement| requirement.name)
+        .collect();
+    let imported = if recover {
+        python_setup.verify_imports(&required_packages)
+    } else {
+        python_setup.verify_imports_only(&required_packages)
+    };
+    if let Err(e) = imported {
+        error!("{}", e);
+        return Err(anyhow!("Ошибка импорта: {}", e));
+    }
+    Ok(required_packages.len())
+}
+
+/// Graceful shutdown по первому сигналу; повторный сигнал до завершения очистки -
+/// немедленный выход (exit 130). Очистка, не уложившаяся только в таймауты шагов, не считается
+/// фатальной: процесс завершается с кодом 0, остальные ошибки остановки - exit 1
+async fn graceful_shutdown(
+    config: &SystemConfig,
+    cleanup: &CleanupConfig,
+    pid: i32,
+    shutdown_order: ShutdownOrder,
+    second_signal: impl std::future::Future<Output = ()>,
+) {
+    info!("Initiating graceful shutdown... (send the signal again to force quit)");
+    let first_signal = Instant::now();
+
+    let shutdown = platform_specific::shutdown::run(config, cleanup, pid, shutdown_order);
+
+    tokio::select! {
+        result = shutdown => match result {
+            Ok(()) => {
+                info!("Shutdown completed, exiting");
+                std::process::exit(0);
+            }
+            Err(ShutdownError::CleanupFailed(failures))
+                if failures.iter().all(|failure| matches!(failure, ShutdownError::CleanupTimeout { .. })) =>
+            {
+                warn!("Cleanup incomplete ({} step(s) timed out), exiting anyway", failures.len());
+                std::process::exit(0);
+            }
+            Err(e) => {
+                error!("Critical shutdown error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        _ = second_signal => {
+            warn!("Second signal {:?} after the first one, force quitting", first_signal.elapsed());
+            std::process::exit(130);
+        }
+    }
+}
+
+/// KEEP_BROWSER_OPEN_ON_ERROR для одного устройства. С таймаутом автоматизации не
+/// поддерживается: ожидание оператора входило бы в таймаут и прерывалось им
+fn keep_browser_open_on_error(automation_timeout: Option<Duration>) -> bool {
+    if !utils::env_flag("KEEP_BROWSER_OPEN_ON_ERROR", false) {
+        return false;
+    }
+    if let Some(automation_timeout) = automation_timeout {
+        warn!(
+            "KEEP_BROWSER_OPEN_ON_ERROR игнорируется: задан таймаут автоматизации {:?} (automation_timeout_secs=0 отключает его)",
+            automation_timeout
+        );
+        return false;
+    }
+    true
+}
+
+/// Добавляет параметры устройства: device_id, выходную директорию запуска, режим контекста
+/// браузера и прокси. Без `device_id` параметры берутся у Android устройства по умолчанию
+/// (Python сторона запускает Chromium), а если его нет среди профилей - у первого устройства
+async fn insert_device_kwargs(
+    kwargs: &mut AutomationKwargs,
+    device_id: Option<&str>,
+    run_context: &RunContext,
+) -> Result<()> {
+    if let Some(device_id) = device_id {
+        kwargs.insert("device_id".to_string(), json!(device_id));
+    }
+    kwargs.insert("output_dir".to_string(), json!(run_context.output_dir(device_id).to_string_lossy()));
+
+    let metadata_device = match device_id {
+        Some(device_id) => device_id.to_string(),
+        None => {
+            let device_ids = emulation::list_devices().await?;
+            if device_ids.iter().any(|id| id == emulation::DEFAULT_ANDROID_DEVICE_ID) {
+                emulation::DEFAULT_ANDROID_DEVICE_ID.to_string()
+            } else {
+                device_ids.into_iter().next().ok_or_else(|| anyhow!("Нет инициализированных устройств"))?
+            }
+        }
+    };
+    let device = emulation::get_device(&metadata_device).await?;
+    let device_metadata = &device.metadata;
+    kwargs.insert("context_mode".to_string(), json!(device_metadata.context_mode.as_str()));
+    // executable_path (BROWSER_EXECUTABLE) и дополнительные аргументы Chromium
+    kwargs.insert("launch_options".to_string(), device.to_playwright_launch_options());
+    // Эмуляция устройства в контексте: viewport, user agent, заголовки, разрешения и init scripts
+    // (navigator.hardwareConcurrency/deviceMemory, saveData, WebGL, шум canvas)
+    kwargs.insert("context_options".to_string(), device.to_playwright_context_options());
+    kwargs.insert("init_scripts".to_string(), json!(device.init_scripts()?));
+    kwargs.insert("permission_overrides".to_string(), json!(device.permission_overrides()));
+    if let Some(proxy) = &device_metadata.connection_info.proxy {
+        kwargs.insert("proxy".to_string(), proxy.to_playwright_proxy());
+    }
+    if let Some(user_data_dir) = &device_metadata.user_data_dir {
+        fs::create_dir_all(user_data_dir)?;
+        kwargs.insert("user_data_dir".to_string(), json!(user_data_dir.to_string_lossy()));
+    }
+    Ok(())
 }
\ No newline at end of file
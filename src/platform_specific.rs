@@ -1,40 +1,161 @@
-use crate::config::SystemConfig;
-use anyhow::Result;
+use crate::config::{CleanupConfig, GcStrategy, ShutdownOrder, ShutdownState, SystemConfig};
+use crate::errors::ShutdownError;
 use log::{error, info};
 use pyo3::Python;
 
+type Result<T> = std::result::Result<T, ShutdownError>;
+
 pub mod cleanup {
     use super::*;
+    use std::future::Future;
     use std::time::Duration;
     use tokio::time::timeout;
-    use std::fs;
-
-    pub async fn cleanup_resources(_config: &SystemConfig) -> Result<()> {
-        const CLEANUP_TIMEOUT: Duration = Duration::from_secs(5);
-        
-        timeout(CLEANUP_TIMEOUT, async {
-            info!("Starting cleanup process...");
-            
-            // Очистка Python ресурсов
-            Python::with_gil(|py| {
-                if let Err(e) = py.run("import gc; gc.collect()", None, None) {
-                    error!("Python cleanup error: {}", e);
-                }
-            });
 
-            // Очистка кэша
-            let cache_path = std::env::current_dir()?.join("target").join("playwright-cache");
-            if cache_path.exists() {
-                if let Err(e) = fs::remove_dir_all(&cache_path) {
-                    error!("Cache cleanup error: {}", e);
-                }
-            }
+    /// Выполняет все шаги очистки, каждый под собственным таймаутом: медленный или упавший
+    /// шаг не отменяет остальные. Ошибки шагов собираются в `ShutdownError::CleanupFailed`.
+    /// Скачанные браузеры Playwright удаляются только при `playwright_cache` (CLEANUP_PLAYWRIGHT_CACHE),
+    /// иначе следующий запуск переиспользует кэш (процессы браузеров закрывает action.shutdown())
+    pub async fn cleanup_resources(_config: &SystemConfig, cleanup: &CleanupConfig) -> Result<()> {
+        let step_timeout = cleanup.step_timeout();
+        info!("Starting cleanup process (step timeout {:?})...", step_timeout);
+
+        let mut failed = Vec::new();
+        run_step("python gc", step_timeout, python_gc(), &mut failed).await;
+        let remove_heartbeat = async {
+            crate::utils::remove_heartbeat_file().map_err(|e| step_error("heartbeat file", e))
+        };
+        run_step("heartbeat file", step_timeout, remove_heartbeat, &mut failed).await;
+        if cleanup.playwright_cache() {
+            run_step("playwright cache", step_timeout, remove_playwright_cache(), &mut failed).await;
+        } else {
+            info!("Keeping Playwright cache (playwright_cache=false)");
+        }
+
+        if !failed.is_empty() {
+            return Err(ShutdownError::CleanupFailed(failed));
+        }
+        info!("Cleanup completed");
+        Ok(())
+    }
+
+    async fn run_step(
+        step: &'static str,
+        step_timeout: Duration,
+        future: impl Future<Output = Result<()>>,
+        failed: &mut Vec<ShutdownError>,
+    ) {
+        let error = match timeout(step_timeout, future).await {
+            Ok(Ok(())) => return,
+            Ok(Err(e)) => e,
+            Err(_) => ShutdownError::CleanupTimeout { step, timeout: step_timeout },
+        };
+        error!("{}", error);
+        failed.push(error);
+    }
 
-            info!("Cleanup completed");
-            Ok(())
+    fn step_error(step: &'static str, error: impl std::fmt::Display) -> ShutdownError {
+        ShutdownError::CleanupStepError { step, reason: error.to_string() }
+    }
+
+    /// Очистка Python ресурсов в blocking потоке, чтобы таймаут шага мог сработать
+    async fn python_gc() -> Result<()> {
+        let gc_strategy = GcStrategy::from_env().unwrap_or_else(|e| {
+            error!("{}, using default GC strategy", e);
+            GcStrategy::default()
+        });
+        let Some(snippet) = gc_strategy.snippet() else {
+            return Ok(());
+        };
+        tokio::task::spawn_blocking(move || {
+            Python::with_gil(|py| py.run(snippet, None, None))
+                .map_err(|e| ShutdownError::PythonCleanupError(e.to_string()))
         })
         .await
-        .map_err(|_| anyhow::anyhow!("Cleanup timeout exceeded"))?
+        .map_err(|e| ShutdownError::PythonCleanupError(e.to_string()))?
+    }
+
+    async fn remove_playwright_cache() -> Result<()> {
+        const STEP: &str = "playwright cache";
+        // Тот же кэш, что выбрал PythonSetup: ensure_environment выставляет PLAYWRIGHT_BROWSERS_PATH
+        let Some(cache_path) = std::env::var_os("PLAYWRIGHT_BROWSERS_PATH").map(std::path::PathBuf::from) else {
+            info!("PLAYWRIGHT_BROWSERS_PATH is not set, skipping Playwright cache removal");
+            return Ok(());
+        };
+        if tokio::fs::try_exists(&cache_path).await.map_err(|e| step_error(STEP, e))? {
+            tokio::fs::remove_dir_all(&cache_path).await.map_err(|e| step_error(STEP, e))?;
+        }
+        Ok(())
+    }
+}
+
+pub mod shutdown {
+    use super::*;
+    use std::future::Future;
+
+    /// Полная последовательность graceful shutdown. После флага остановки всегда вызывается
+    /// необязательный `action.shutdown()`, чтобы Python закрыл сессии до завершения процесса.
+    ///
+    /// `CleanupFirst`: флаг остановки → `cleanup_resources` → состояние `Completed` →
+    /// завершение дочерних процессов (для собственного `pid`) или процесса `pid`.
+    ///
+    /// `SignalFirst`: флаг остановки → завершение процессов → очистка → `Completed`.
+    ///
+    /// Оба шага выполняются всегда: ошибка очистки не оставляет браузеры без сигнала.
+    /// Собственный процесс не завершается, код выхода по результату выбирает вызывающий
+    pub async fn run(config: &SystemConfig, cleanup: &CleanupConfig, pid: i32, order: ShutdownOrder) -> Result<()> {
+        info!("Shutdown sequence: {:?}", order);
+        config.request_shutdown();
+        python_shutdown_hook().await;
+
+        run_steps(config, order, cleanup::cleanup_resources(config, cleanup), terminate(config, pid)).await
+    }
+
+    /// Порядок шагов остановки. Ошибка завершения процессов важнее ошибки очистки:
+    /// ошибки шагов очистки уже записаны в лог, а неостановленные процессы остаются жить
+    pub(crate) async fn run_steps(
+        config: &SystemConfig,
+        order: ShutdownOrder,
+        cleanup: impl Future<Output = Result<()>>,
+        terminate: impl Future<Output = Result<()>>,
+    ) -> Result<()> {
+        let run_cleanup = async {
+            let result = cleanup.await;
+            if result.is_ok() {
+                config.set_shutdown_state(ShutdownState::Completed);
+            }
+            result
+        };
+        let (cleanup_result, terminate_result) = match order {
+            ShutdownOrder::CleanupFirst => {
+                let cleanup_result = run_cleanup.await;
+                (cleanup_result, terminate.await)
+            }
+            ShutdownOrder::SignalFirst => {
+                let terminate_result = terminate.await;
+                (run_cleanup.await, terminate_result)
+            }
+        };
+        terminate_result?;
+        cleanup_result
+    }
+
+    /// Ошибки хука не прерывают остановку: процесс завершается как без него
+    async fn python_shutdown_hook() {
+        match crate::py_automation::run_shutdown_hook().await {
+            Ok(true) => info!("Python shutdown hook completed"),
+            Ok(false) => info!("No Python shutdown hook, skipping"),
+            Err(e) => error!("Python shutdown hook error: {}", e),
+        }
+    }
+
+    async fn terminate(config: &SystemConfig, pid: i32) -> Result<()> {
+        #[cfg(unix)]
+        unix::handle_shutdown(config, pid).await?;
+
+        #[cfg(windows)]
+        windows::handle_shutdown(config, pid).await?;
+
+        Ok(())
     }
 }
 
@@ -44,50 +165,250 @@ pub mod unix {
     use nix::sys::signal::{self, Signal};
     use nix::unistd::Pid;
 
+    use log::warn;
+    use std::time::Duration;
+
+    // Пауза между SIGTERM и SIGKILL по умолчанию
+    const DEFAULT_SIGKILL_GRACE_MS: u64 = 5000;
+
+    /// Пауза перед SIGKILL из UNIX_SIGKILL_GRACE_MS
+    fn sigkill_grace() -> Duration {
+        let millis = std::env::var("UNIX_SIGKILL_GRACE_MS")
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SIGKILL_GRACE_MS);
+        Duration::from_millis(millis)
+    }
+
+    /// Для собственного процесса сигналы получают только его потомки (драйвер Playwright и
+    /// процессы браузеров): SIGTERM, а оставшиеся после grace периода - SIGKILL. Сам процесс
+    /// не завершается - SIGTERM самому себе перехватил бы обработчик сигналов в main, выход
+    /// выполняет main. Чужой процесс `pid` завершается той же эскалацией
     pub async fn handle_shutdown(config: &SystemConfig, pid: i32) -> Result<()> {
         info!("Handling Unix shutdown...");
-        if let Err(e) = signal::kill(Pid::from_raw(pid), Signal::SIGTERM) {
-            error!("SIGTERM error: {}", e);
-            return Err(anyhow::anyhow!("Failed to send SIGTERM: {}", e));
+        if config.shutdown_state() == ShutdownState::Running {
+            config.request_shutdown();
+        }
+        if pid as u32 == std::process::id() {
+            let children = descendants(pid).await;
+            if children.is_empty() {
+                return Ok(());
+            }
+            info!("Terminating {} child processes: {:?}", children.len(), children);
+            return terminate_with_grace(&children).await;
+        }
+
+        terminate_with_grace(&[pid]).await
+    }
+
+    /// SIGTERM всем `pids`, а процессам, игнорирующим его дольше grace периода, - SIGKILL.
+    /// Уже завершившиеся процессы (ESRCH) пропускаются
+    async fn terminate_with_grace(pids: &[i32]) -> Result<()> {
+        let signal_error = |pid: i32, signal: &'static str, e: nix::Error| ShutdownError::SignalError {
+            pid,
+            signal,
+            reason: e.to_string(),
+        };
+
+        let mut signalled = Vec::new();
+        for &pid in pids {
+            match signal::kill(Pid::from_raw(pid), Signal::SIGTERM) {
+                Ok(()) => signalled.push(pid),
+                Err(nix::Error::ESRCH) => {}
+                Err(e) => return Err(signal_error(pid, "SIGTERM", e)),
+            }
+        }
+        if signalled.is_empty() {
+            return Ok(());
+        }
+
+        let grace = sigkill_grace();
+        tokio::time::sleep(grace).await;
+        for pid in signalled {
+            let target = Pid::from_raw(pid);
+            if signal::kill(target, None).is_ok() {
+                warn!("Process {} still alive {:?} after SIGTERM, sending SIGKILL", pid, grace);
+                match signal::kill(target, Signal::SIGKILL) {
+                    Ok(()) | Err(nix::Error::ESRCH) => {}
+                    Err(e) => return Err(signal_error(pid, "SIGKILL", e)),
+                }
+            }
         }
-        config.request_shutdown()?;
         Ok(())
     }
+
+    /// Все потомки процесса по таблице `ps` (pid и ppid), начиная с ближайших.
+    /// Если `ps` недоступен, потомков нет
+    async fn descendants(pid: i32) -> Vec<i32> {
+        let output = match tokio::process::Command::new("ps").args(["-A", "-o", "pid=", "-o", "ppid="]).output().await {
+            Ok(output) if output.status.success() => output,
+            Ok(output) => {
+                warn!("ps exited with {}, child processes are not tracked", output.status);
+                return Vec::new();
+            }
+            Err(e) => {
+                warn!("Failed to run ps, child processes are not tracked: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let table: Vec<(i32, i32)> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace().map(str::parse::<i32>);
+                match (fields.next(), fields.next()) {
+                    (Some(Ok(child)), Some(Ok(parent))) => Some((child, parent)),
+                    _ => None,
+                }
+            })
+            .collect();
+
+        let mut found = Vec::new();
+        let mut index = 0;
+        let mut parents = vec![pid];
+        while index < parents.len() {
+            let parent = parents[index];
+            for &(child, _) in table.iter().filter(|(child, ppid)| *ppid == parent && *child != pid) {
+                if !found.contains(&child) {
+                    found.push(child);
+                    parents.push(child);
+                }
+            }
+            index += 1;
+        }
+        found
+    }
 }
 
 #[cfg(windows)]
 pub mod windows {
     use super::*;
-    use windows_sys::Win32::Foundation::CloseHandle;
+    use log::warn;
+    use windows_sys::Win32::Foundation::{CloseHandle, GetLastError};
     use windows_sys::Win32::System::Threading::{OpenProcess, TerminateProcess, PROCESS_TERMINATE};
 
+    /// Собственный процесс не завершается: выход после очистки выполняет main.
+    /// Чужой процесс `pid` завершается через TerminateProcess
     pub async fn handle_shutdown(config: &SystemConfig, pid: i32) -> Result<()> {
         info!("Handling Windows shutdown...");
-        
+        if pid as u32 == std::process::id() {
+            if config.shutdown_state() == ShutdownState::Running {
+                config.request_shutdown();
+            }
+            return Ok(());
+        }
+
         unsafe {
-            let handle = OpenProcess(
-                PROCESS_TERMINATE,
-                0,                
-                pid as u32 
-            );
-            
+            let handle = OpenProcess(PROCESS_TERMINATE, 0, pid as u32);
+
             if handle == 0 {
-                return Err(anyhow::anyhow!("Failed to get process handle"));
+                // Например, ограниченная служебная учетная запись: процесс не завершаем,
+                // остановку выполняет graceful путь по флагу
+                warn!(
+                    "Failed to open process {} for termination (error {}), falling back to graceful shutdown only",
+                    pid,
+                    GetLastError()
+                );
+                if config.shutdown_state() == ShutdownState::Running {
+                    config.request_shutdown();
+                }
+                return Ok(());
             }
 
             let terminate_result = TerminateProcess(handle, 0);
+            let terminate_error = GetLastError();
             let close_result = CloseHandle(handle);
 
             if terminate_result == 0 {
-                return Err(anyhow::anyhow!("Failed to terminate process"));
+                return Err(ShutdownError::ProcessHandleError {
+                    pid,
+                    reason: format!("TerminateProcess failed (error {})", terminate_error),
+                });
             }
 
             if close_result == 0 {
-                return Err(anyhow::anyhow!("Failed to close handle"));
+                return Err(ShutdownError::ProcessHandleError {
+                    pid,
+                    reason: format!("CloseHandle failed (error {})", GetLastError()),
+                });
             }
         }
 
-        config.request_shutdown()?;
+        if config.shutdown_state() == ShutdownState::Running {
+            config.request_shutdown();
+        }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Steps = Arc<Mutex<Vec<&'static str>>>;
+
+    async fn step(steps: Steps, name: &'static str, result: Result<()>) -> Result<()> {
+        steps.lock().unwrap().push(name);
+        result
+    }
+
+    fn cleanup_timeout() -> ShutdownError {
+        ShutdownError::CleanupFailed(vec![ShutdownError::CleanupTimeout {
+            step: "python gc",
+            timeout: std::time::Duration::from_secs(1),
+        }])
+    }
+
+    #[tokio::test]
+    async fn cleanup_first_runs_cleanup_then_terminate() {
+        let config = SystemConfig::new();
+        let steps = Steps::default();
+        shutdown::run_steps(
+            &config,
+            ShutdownOrder::CleanupFirst,
+            step(steps.clone(), "cleanup", Ok(())),
+            step(steps.clone(), "terminate", Ok(())),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(*steps.lock().unwrap(), ["cleanup", "terminate"]);
+        assert_eq!(config.shutdown_state(), ShutdownState::Completed);
+    }
+
+    #[tokio::test]
+    async fn signal_first_runs_terminate_then_cleanup() {
+        let config = SystemConfig::new();
+        let steps = Steps::default();
+        shutdown::run_steps(
+            &config,
+            ShutdownOrder::SignalFirst,
+            step(steps.clone(), "cleanup", Ok(())),
+            step(steps.clone(), "terminate", Ok(())),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(*steps.lock().unwrap(), ["terminate", "cleanup"]);
+        assert_eq!(config.shutdown_state(), ShutdownState::Completed);
+    }
+
+    #[tokio::test]
+    async fn failed_cleanup_still_terminates() {
+        let config = SystemConfig::new();
+        config.request_shutdown();
+        let steps = Steps::default();
+        let result = shutdown::run_steps(
+            &config,
+            ShutdownOrder::CleanupFirst,
+            step(steps.clone(), "cleanup", Err(cleanup_timeout())),
+            step(steps.clone(), "terminate", Ok(())),
+        )
+        .await;
+
+        assert!(matches!(result, Err(ShutdownError::CleanupFailed(_))));
+        assert_eq!(*steps.lock().unwrap(), ["cleanup", "terminate"]);
+        assert_eq!(config.shutdown_state(), ShutdownState::ShuttingDown);
+    }
+}
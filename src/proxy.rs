@@ -0,0 +1,209 @@
+use anyhow::{Result, anyhow};
+use chromiumoxide::Page;
+use chromiumoxide::cdp::browser_protocol::fetch::{
+    AuthChallengeResponse, AuthChallengeResponseResponse, ContinueRequestParams, ContinueWithAuthParams,
+    EnableParams, EventAuthRequired, EventRequestPaused,
+};
+use futures::StreamExt;
+use futures::future::join_all;
+use log::{info, warn};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+// Таймаут проверки одного прокси по умолчанию (секунды)
+const DEFAULT_HEALTH_TIMEOUT_SECS: u64 = 5;
+
+/// Прокси эмулируемого устройства
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProxyConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    /// Разбирает строку вида `host:port` или `user:pass@host:port` (схема `http://` допускается)
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let without_scheme = value.split_once("://").map_or(value, |(_, rest)| rest);
+
+        let (credentials, address) = match without_scheme.rsplit_once('@') {
+            Some((credentials, address)) => (Some(credentials), address),
+            None => (None, without_scheme),
+        };
+
+        let (host, port) = address
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("Прокси '{}' должен быть в формате host:port", value))?;
+        if host.is_empty() {
+            return Err(anyhow!("Пустой хост прокси: '{}'", value));
+        }
+        let port = port
+            .parse::<u16>()
+            .map_err(|_| anyhow!("Некорректный порт прокси: '{}'", value))?;
+
+        let (username, password) = match credentials {
+            Some(credentials) => match credentials.split_once(':') {
+                Some((user, pass)) => (Some(user.to_string()), Some(pass.to_string())),
+                None => (Some(credentials.to_string()), None),
+            },
+            None => (None, None),
+        };
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            username,
+            password,
+        })
+    }
+
+    /// Адрес без учетных данных, безопасный для логов
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// Прокси требует авторизации. Учетные данные в `--proxy-server=user:pass@host` Chromium
+    /// не использует, поэтому такие прокси передаются через опцию контекста Playwright
+    /// или `enable_proxy_auth` для chromiumoxide
+    pub fn has_credentials(&self) -> bool {
+        self.username.is_some()
+    }
+
+    /// Флаги запуска Chromium (chromiumoxide). Учетные данные во флаг не попадают,
+    /// их передает `enable_proxy_auth`
+    pub fn chromium_args(&self) -> Vec<String> {
+        vec![format!("--proxy-server=http://{}", self.address())]
+    }
+
+    /// Опция `proxy` Playwright (`server`, `username`, `password`)
+    pub fn to_playwright_proxy(&self) -> Value {
+        let mut proxy = json!({ "server": format!("http://{}", self.address()) });
+        if let Some(username) = &self.username {
+            proxy["username"] = json!(username);
+            proxy["password"] = json!(self.password.clone().unwrap_or_default());
+        }
+        proxy
+    }
+}
+
+/// Авторизация на прокси для страницы chromiumoxide через перехват Fetch: на `authRequired`
+/// отвечает учетными данными `proxy`, остальные приостановленные запросы пропускает без
+/// изменений. Для прокси без учетных данных ничего не делает и возвращает `None`.
+/// Задача завершается вместе со страницей
+pub async fn enable_proxy_auth(page: &Page, proxy: &ProxyConfig) -> Result<Option<JoinHandle<()>>> {
+    let Some(username) = proxy.username.clone() else {
+        return Ok(None);
+    };
+    let password = proxy.password.clone().unwrap_or_default();
+
+    let mut auth_required = page.event_listener::<EventAuthRequired>().await?;
+    let mut request_paused = page.event_listener::<EventRequestPaused>().await?;
+    page.execute(EnableParams {
+        patterns: None,
+        handle_auth_requests: Some(true),
+    })
+    .await
+    .map_err(|e| anyhow!("Не удалось включить перехват запросов для прокси {}: {}", proxy.address(), e))?;
+
+    let page = page.clone();
+    let address = proxy.address();
+    Ok(Some(tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                event = auth_required.next() => {
+                    let Some(event) = event else { break };
+                    let response = AuthChallengeResponse {
+                        response: AuthChallengeResponseResponse::ProvideCredentials,
+                        username: Some(username.clone()),
+                        password: Some(password.clone()),
+                    };
+                    if let Err(e) = page.execute(ContinueWithAuthParams::new(event.request_id.clone(), response)).await {
+                        warn!("Ошибка авторизации на прокси {}: {}", address, e);
+                    }
+                }
+                event = request_paused.next() => {
+                    let Some(event) = event else { break };
+                    if let Err(e) = page.execute(ContinueRequestParams::new(event.request_id.clone())).await {
+                        warn!("Ошибка продолжения запроса через прокси {}: {}", address, e);
+                    }
+                }
+            }
+        }
+    })))
+}
+
+/// Пул прокси, распределяемых по устройствам парка
+#[derive(Debug, Clone, Default)]
+pub struct ProxyPool {
+    proxies: Vec<ProxyConfig>,
+}
+
+impl ProxyPool {
+    pub fn new(proxies: Vec<ProxyConfig>) -> Self {
+        Self { proxies }
+    }
+
+    /// Пул из PROXY_POOL (список через запятую); `None`, если переменная не задана или пуста
+    pub fn from_env() -> Result<Option<Self>> {
+        let value = match std::env::var("PROXY_POOL") {
+            Ok(value) if !value.trim().is_empty() => value,
+            _ => return Ok(None),
+        };
+
+        let proxies = value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(ProxyConfig::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Self::new(proxies)))
+    }
+
+    /// Таймаут проверки одного прокси из PROXY_HEALTH_TIMEOUT_SECS
+    pub fn health_timeout() -> Duration {
+        let secs = std::env::var("PROXY_HEALTH_TIMEOUT_SECS")
+            .ok()
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_HEALTH_TIMEOUT_SECS);
+        Duration::from_secs(secs)
+    }
+
+    pub fn proxies(&self) -> &[ProxyConfig] {
+        &self.proxies
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proxies.is_empty()
+    }
+
+    /// Убирает из пула прокси, к которым не удалось открыть TCP соединение за `timeout_duration`.
+    /// Все прокси проверяются параллельно, поэтому проверка занимает не дольше одного таймаута
+    pub async fn filter_healthy(&mut self, timeout_duration: Duration) {
+        let checks = self.proxies.iter().map(|proxy| async move {
+            let address = proxy.address();
+            match timeout(timeout_duration, TcpStream::connect(&address)).await {
+                Ok(Ok(_)) => true,
+                Ok(Err(e)) => {
+                    warn!("Прокси {} недоступен, исключен из пула: {}", address, e);
+                    false
+                }
+                Err(_) => {
+                    warn!("Прокси {} не ответил за {:?}, исключен из пула", address, timeout_duration);
+                    false
+                }
+            }
+        });
+        let healthy = join_all(checks).await;
+
+        let total = self.proxies.len();
+        let mut results = healthy.into_iter();
+        self.proxies.retain(|_| results.next().unwrap_or(false));
+        info!("Проверка прокси: доступно {} из {}", self.proxies.len(), total);
+    }
+}
@@ -1,45 +1,720 @@
 use anyhow::{Result, anyhow};
-use log::{info, error};
+use log::{info, error, warn};
 #[allow(unused_imports)]
-use pyo3::{Python, PyResult, types::IntoPyDict};
+use pyo3::{Python, PyObject, PyResult, types::{IntoPyDict, PyDict}};
+use pyo3::{Py, sync::GILOnceCell, types::{PyCFunction, PyModule, PyTuple}};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use pyo3::exceptions::PyStopAsyncIteration;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
-use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use futures::future::join_all;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::utils::{run_python, with_python};
+use crate::failure_bundle::collect_failure_bundle_logged;
+use crate::config::{SuccessPredicate, SystemConfig};
+use crate::errors::PrecheckFailed;
+use crate::emulation::get_device_automation_timeout;
 
-pub async fn run_automation() -> Result<()> {
+// Сколько ждать завершения корутины после отмены по таймауту (finally блоки, закрытие браузера)
+const CANCEL_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+// Причина отмены запуска при остановке бота
+const SHUTDOWN_CANCEL_REASON: &str = "остановка бота";
+
+// Причина отмены устройства парка в режиме fail-fast
+const FAIL_FAST_CANCEL_REASON: &str = "другое устройство парка завершилось ошибкой (fail-fast)";
+
+// Таймаут action.shutdown() при остановке по Ctrl+C
+const SHUTDOWN_HOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Обертка, через которую корутину автоматизации можно отменить из Rust: запоминает loop и
+// задачу под ключом запуска, `cancel` отменяет задачу потокобезопасно. Если передан
+// `should_stop`, фоновая задача опрашивает его и отменяет корутину при остановке бота.
+// `run_shutdown_hook` выполняет action.shutdown() в каждом работающем loop автоматизации
+// (Telethon клиенты и браузеры привязаны к своему loop), а без них - в новом loop
+const CANCELLABLE_HELPER: &str = r#"
+import asyncio
+
+_running = {}
+
+SHUTDOWN_POLL_INTERVAL = 0.5
+
+async def _watch_shutdown(should_stop, task):
+    while not should_stop():
+        await asyncio.sleep(SHUTDOWN_POLL_INTERVAL)
+    task.cancel()
+
+async def run_cancellable(key, coroutine, should_stop=None):
+    task = asyncio.current_task()
+    _running[key] = (asyncio.get_running_loop(), task)
+    watcher = asyncio.create_task(_watch_shutdown(should_stop, task)) if should_stop else None
+    try:
+        return await coroutine
+    finally:
+        if watcher:
+            watcher.cancel()
+        _running.pop(key, None)
+
+def cancel(key):
+    entry = _running.get(key)
+    if entry is None:
+        return False
+    loop, task = entry
+    loop.call_soon_threadsafe(task.cancel)
+    return True
+
+async def _call_hook(hook, timeout):
+    result = hook()
+    if asyncio.iscoroutine(result):
+        await asyncio.wait_for(result, timeout)
+
+def run_shutdown_hook(hook, timeout):
+    loops = {id(loop): loop for loop, _ in list(_running.values()) if loop.is_running()}
+    if not loops:
+        asyncio.run(_call_hook(hook, timeout))
+        return
+    futures = [asyncio.run_coroutine_threadsafe(_call_hook(hook, timeout), loop) for loop in loops.values()]
+    for future in futures:
+        future.result(timeout)
+"#;
+
+static CANCELLABLE_MODULE: GILOnceCell<Py<PyModule>> = GILOnceCell::new();
+
+// Ключи запусков для `CANCELLABLE_HELPER` (в парке несколько запусков идут одновременно)
+static RUN_KEY: AtomicU64 = AtomicU64::new(0);
+
+fn cancellable_module(py: Python<'_>) -> PyResult<&PyModule> {
+    let module = CANCELLABLE_MODULE.get_or_try_init(py, || {
+        PyModule::from_code(py, CANCELLABLE_HELPER, "bombie_cancellable.py", "bombie_cancellable").map(Into::into)
+    })?;
+    Ok(module.as_ref(py))
+}
+
+/// Именованные аргументы для точки входа автоматизации (имя -> JSON значение)
+pub type AutomationKwargs = Map<String, Value>;
+
+/// Конвертирует JSON значение в Python объект (dict, list, ...) через json.loads
+fn json_to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    let serialized = value.to_string();
+    let object = py.import("json")?
+        .getattr("loads")?
+        .call1((serialized,))?;
+    Ok(object.into())
+}
+
+/// Конвертирует именованные аргументы в Python dict для `call((), Some(kwargs))`
+fn kwargs_to_py<'py>(py: Python<'py>, kwargs: &AutomationKwargs) -> PyResult<&'py PyDict> {
+    let dict = PyDict::new(py);
+    for (key, value) in kwargs {
+        dict.set_item(key, json_to_py(py, value)?)?;
+    }
+    Ok(dict)
+}
+
+/// Конвертирует Python объект в JSON через json.dumps (неизвестные типы - строкой)
+fn py_to_json(py: Python<'_>, object: &pyo3::PyAny) -> PyResult<Value> {
+    let json = py.import("json")?;
+    let kwargs = [("default", py.import("builtins")?.getattr("str")?)].into_py_dict(py);
+    let serialized: String = json.getattr("dumps")?.call((object,), Some(kwargs))?.extract()?;
+    serde_json::from_str(&serialized)
+        .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
+
+/// Выполняет корутину до завершения. Без активного event loop в потоке используется
+/// asyncio.run. Если loop уже запущен (встраивание в Jupyter/uvloop, повторный вход),
+/// asyncio.run упадет с RuntimeError, поэтому корутина выполняется в отдельном потоке
+/// со своим loop, а текущий поток ждет результат
+fn run_coroutine<'py>(py: Python<'py>, coroutine: &'py pyo3::PyAny) -> PyResult<&'py pyo3::PyAny> {
+    let asyncio = py.import("asyncio")?;
+    let running_loop = asyncio.getattr("_get_running_loop")?.call0()?;
+
+    if running_loop.is_none() {
+        return asyncio.getattr("run")?.call1((coroutine,)).map_err(|e| {
+            let message = e.value(py).to_string();
+            if e.is_instance_of::<pyo3::exceptions::PyRuntimeError>(py) && message.contains("running event loop") {
+                pyo3::exceptions::PyRuntimeError::new_err(format!(
+                    "Event loop уже запущен в этом потоке, asyncio.run недоступен: {}", message
+                ))
+            } else {
+                e
+            }
+        });
+    }
+
+    warn!("Event loop уже запущен в текущем потоке, корутина выполняется в отдельном потоке");
+    let executor = py.import("concurrent.futures")?
+        .getattr("ThreadPoolExecutor")?
+        .call1((1,))?;
+    let future = executor.call_method1("submit", (asyncio.getattr("run")?, coroutine))?;
+    let result = future.call_method0("result");
+    executor.call_method0("shutdown")?;
+    result
+}
+
+/// Вызывает action.initialize_automation и выполняет корутину через `run_coroutine`.
+/// Корутина регистрируется под `run_key`, чтобы ее можно было отменить по таймауту.
+/// Дополнительно передается `should_stop()` - True после запроса остановки бота: Python может
+/// проверять его между шагами, а при остановке корутина отменяется и сама
+fn call_initialize_automation(
+    py: Python<'_>,
+    kwargs: &AutomationKwargs,
+    run_key: u64,
+    system: Arc<SystemConfig>,
+) -> PyResult<Value> {
+    let automation_module = py.import("action")?;
+    let kwargs = kwargs_to_py(py, kwargs)?;
+    let should_stop = PyCFunction::new_closure(
+        py,
+        Some("should_stop\0"),
+        None,
+        move |_args: &PyTuple, _kwargs: Option<&PyDict>| system.is_shutting_down(),
+    )?;
+    kwargs.set_item("should_stop", should_stop)?;
+    
+    // initialize_automation сама инициализирует логин, 
+    // параметры автоматизации передаются именованными аргументами
+    let coroutine = automation_module
+        .getattr("initialize_automation")?
+        .call((), Some(kwargs))?;
+    let coroutine = cancellable_module(py)?
+        .getattr("run_cancellable")?
+        .call1((run_key, coroutine, should_stop))?;
+    
+    // Запускаем корутину
+    let returned = run_coroutine(py, coroutine)?;
+    
+    py_to_json(py, returned)
+}
+
+/// Вызывает функцию предварительной проверки из модуля action с теми же kwargs.
+/// Поддерживаются обычные и async функции; результат приводится к bool по правилам Python
+fn call_precheck(py: Python<'_>, name: &str, kwargs: &AutomationKwargs) -> PyResult<bool> {
+    let kwargs = kwargs_to_py(py, kwargs)?;
+    let mut returned = py.import("action")?
+        .getattr(name)?
+        .call((), Some(kwargs))?;
+
+    let asyncio = py.import("asyncio")?;
+    if asyncio.getattr("iscoroutine")?.call1((returned,))?.is_true()? {
+        returned = run_coroutine(py, returned)?;
+    }
+    returned.is_true()
+}
+
+/// Запускает предварительную проверку AUTOMATION_PRECHECK (имя функции в action.py) с общим
+/// таймаутом автоматизации в blocking потоке, как и саму автоматизацию.
+/// Ложный результат или исключение - `PrecheckFailed`
+async fn run_precheck(kwargs: &AutomationKwargs, default_timeout: Option<Duration>) -> Result<()> {
+    let name = match std::env::var("AUTOMATION_PRECHECK") {
+        Ok(name) if !name.trim().is_empty() => name.trim().to_string(),
+        _ => return Ok(()),
+    };
+
+    info!("Предварительная проверка action.{}...", name);
+    let precheck_name = name.clone();
+    let kwargs = kwargs.clone();
+    let precheck = run_python(move |py| call_precheck(py, &precheck_name, &kwargs));
+    let passed = match default_timeout {
+        Some(precheck_timeout) => timeout(precheck_timeout, precheck)
+            .await
+            .map_err(|_| PrecheckFailed { precheck: name.clone(), reason: "таймаут".to_string() })?,
+        None => precheck.await,
+    }
+    .map_err(|e| PrecheckFailed { precheck: name.clone(), reason: e.to_string() })?;
+
+    if !passed {
+        return Err(PrecheckFailed { precheck: name, reason: "вернула ложное значение".to_string() }.into());
+    }
+    info!("Предварительная проверка пройдена");
+    Ok(())
+}
+
+/// Запускает initialize_automation, передавая `kwargs` именованными аргументами
+/// (automation_config, heartbeat_file, ...). Возвращает `RunOutcome` с результатом корутины
+/// в виде JSON в `data`. Если задан AUTOMATION_PRECHECK, сначала выполняется проверка, а при
+/// ее неудаче автоматизация пропускается со статусом `PrecheckFailed`.
+/// `default_timeout` (`RunConfig::automation_timeout`) действует, если у устройства нет своего.
+/// После запроса остановки (`SystemConfig::is_shutting_down`) следующие шаги не запускаются,
+/// а уже идущая корутина отменяется; итог в обоих случаях - `Cancelled`.
+///
+/// Event loop автоматизации всегда работает в отдельном blocking потоке, а Rust ожидает
+/// результат асинхронно: поток runtime свободен, поэтому таймаут срабатывает и корутина
+/// отменяется, а остальной Python код (через `run_python`) выполняется, пока loop ждет I/O
+/// и отпускает GIL
+pub async fn run_automation(
+    kwargs: &AutomationKwargs,
+    default_timeout: Option<Duration>,
+    system: &Arc<SystemConfig>,
+) -> RunOutcome {
+    run_automation_cancellable(kwargs, default_timeout, system, &CancellationToken::new()).await
+}
+
+/// `run_automation` со строковыми параметрами (id устройства, целевой URL и т.п.): каждый
+/// параметр передается в `initialize_automation(**kwargs)` строкой
+pub async fn run_automation_with_args(
+    kwargs: HashMap<String, String>,
+    default_timeout: Option<Duration>,
+    system: &Arc<SystemConfig>,
+) -> RunOutcome {
+    let kwargs: AutomationKwargs = kwargs.into_iter().map(|(name, value)| (name, Value::String(value))).collect();
+    run_automation(&kwargs, default_timeout, system).await
+}
+
+/// `run_automation` без параметров: `initialize_automation()` со значениями по умолчанию
+pub async fn run_automation_without_args(default_timeout: Option<Duration>, system: &Arc<SystemConfig>) -> RunOutcome {
+    run_automation_with_args(HashMap::new(), default_timeout, system).await
+}
+
+/// `run_automation`, которую дополнительно отменяет `cancel` (fail-fast парка): идущая корутина
+/// отменяется через `cancel_automation`, итог - `Cancelled`
+async fn run_automation_cancellable(
+    kwargs: &AutomationKwargs,
+    default_timeout: Option<Duration>,
+    system: &Arc<SystemConfig>,
+    cancel: &CancellationToken,
+) -> RunOutcome {
     info!("Запуск автоматизации...");
+    let started = Instant::now();
+
+    if system.is_shutting_down() {
+        return RunOutcome::cancelled(started.elapsed(), SHUTDOWN_CANCEL_REASON);
+    }
+    if cancel.is_cancelled() {
+        return RunOutcome::cancelled(started.elapsed(), FAIL_FAST_CANCEL_REASON);
+    }
+
+    // Окружение не готово - это не ошибка автоматизации, архив ошибки не собираем
+    if let Err(e) = run_precheck(kwargs, default_timeout).await {
+        error!("{}", e);
+        return RunOutcome::precheck_failed(started.elapsed(), &e);
+    }
+
+    if system.is_shutting_down() {
+        return RunOutcome::cancelled(started.elapsed(), SHUTDOWN_CANCEL_REASON);
+    }
+    if cancel.is_cancelled() {
+        return RunOutcome::cancelled(started.elapsed(), FAIL_FAST_CANCEL_REASON);
+    }
     
-    // Создаем Python контекст с таймаутом
-    match timeout(Duration::from_secs(30), async {
-        Python::with_gil(|py| -> PyResult<()> {
-            let automation_module = py.import("action")?;
-            
-            // Вызываем initialize_automation без параметров, 
-            // так как теперь она сама инициализирует логин
-            let coroutine = automation_module
-                .getattr("initialize_automation")?
-                .call0()?;
-            
-            // Запускаем корутину
-            py.import("asyncio")?
-                .getattr("run")?
-                .call1((coroutine,))?;
-            
-            Ok(())
+    // Создаем Python контекст с таймаутом (собственный таймаут устройства важнее общего)
+    let device_id = kwargs.get("device_id").and_then(Value::as_str);
+    let automation_timeout = automation_timeout(device_id, default_timeout).await;
+    let main_kwargs = kwargs.clone();
+    let run_key = RUN_KEY.fetch_add(1, Ordering::Relaxed);
+    let automation_system = Arc::clone(system);
+    let mut automation = Box::pin(run_python(
+        move |py| call_initialize_automation(py, &main_kwargs, run_key, automation_system),
+    ));
+    let waited = async {
+        match automation_timeout {
+            Some(automation_timeout) => timeout(automation_timeout, &mut automation).await.ok(),
+            None => Some(automation.as_mut().await),
+        }
+    };
+    // Отмена проверяется первой, чтобы отмененное устройство не продолжало работу
+    let completed = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => None,
+        completed = waited => Some(completed),
+    };
+    let Some(completed) = completed else {
+        info!("Автоматизация отменена: {}", FAIL_FAST_CANCEL_REASON);
+        cancel_automation(run_key, automation).await;
+        return RunOutcome::cancelled(started.elapsed(), FAIL_FAST_CANCEL_REASON);
+    };
+    let (status, result) = match completed {
+        Some(Ok(value)) => {
+            info!("Автоматизация успешно завершена");
+            (RunStatus::Success, Ok(value))
+        },
+        Some(Err(_)) if system.is_shutting_down() => {
+            info!("Автоматизация остановлена по запросу остановки");
+            return RunOutcome::cancelled(started.elapsed(), SHUTDOWN_CANCEL_REASON);
+        },
+        Some(Err(e)) => {
+            // Ошибка содержит полный Python traceback, логируем его многострочно
+            error!("Ошибка при выполнении автоматизации:\n{}", e);
+            (RunStatus::Failed, Err(e))
+        },
+        None => {
+            let automation_timeout = automation_timeout.unwrap_or_default();
+            error!("Таймаут выполнения автоматизации ({:?}), отменяем корутину", automation_timeout);
+            cancel_automation(run_key, automation).await;
+            (RunStatus::TimedOut, Err(anyhow!("Таймаут автоматизации ({:?})", automation_timeout)))
+        }
+    };
+
+    if let Err(e) = &result {
+        let output_dir = kwargs.get("output_dir").and_then(Value::as_str).map(std::path::Path::new);
+        collect_failure_bundle_logged(device_id, output_dir, crate::logging::log_file(), e).await;
+    }
+
+    let duration = started.elapsed();
+    match result {
+        Ok(value) => RunOutcome::success(duration, value),
+        Err(e) => RunOutcome { status, duration, data: None, error: Some(format!("{:#}", e)) },
+    }
+}
+
+/// Итог автоматизации
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Success,
+    /// Исключение Python или не выполненное условие успеха
+    Failed,
+    TimedOut,
+    /// Предварительная проверка (AUTOMATION_PRECHECK) не пройдена: окружение не готово,
+    /// автоматизация не запускалась. Не повторяется и не считается ошибкой автоматизации
+    PrecheckFailed,
+    /// Запуск отменен извне (например fail-fast парка)
+    Cancelled,
+}
+
+/// Результат запуска автоматизации: статус, длительность, возвращенное Python значение
+/// (если было) и текст ошибки. Возвращается и при неуспехе, чтобы вызывающий код
+/// (повторы, парк, метрики) разбирал результаты одинаково
+#[derive(Debug, Clone, Serialize)]
+pub struct RunOutcome {
+    pub status: RunStatus,
+    pub duration: Duration,
+    pub data: Option<Value>,
+    pub error: Option<String>,
+}
+
+impl RunOutcome {
+    pub fn success(duration: Duration, data: Value) -> Self {
+        Self { status: RunStatus::Success, duration, data: Some(data), error: None }
+    }
+
+    pub fn failed(duration: Duration, error: &anyhow::Error) -> Self {
+        Self { status: RunStatus::Failed, duration, data: None, error: Some(format!("{:#}", error)) }
+    }
+
+    pub fn precheck_failed(duration: Duration, error: &anyhow::Error) -> Self {
+        Self { status: RunStatus::PrecheckFailed, duration, data: None, error: Some(format!("{:#}", error)) }
+    }
+
+    pub fn cancelled(duration: Duration, reason: &str) -> Self {
+        Self { status: RunStatus::Cancelled, duration, data: None, error: Some(reason.to_string()) }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == RunStatus::Success
+    }
+
+    /// Для вызывающего кода, которому важен только факт успеха
+    pub fn into_result(self) -> Result<()> {
+        match self.status {
+            RunStatus::Success => Ok(()),
+            status => Err(anyhow!(
+                "{}",
+                self.error.unwrap_or_else(|| format!("Автоматизация завершилась со статусом {:?}", status))
+            )),
+        }
+    }
+}
+
+/// Отменяет корутину запуска `run_key` (`task.cancel()` в ее event loop) и ждет ее завершения
+/// не дольше `CANCEL_GRACE_PERIOD`, чтобы Python успел выполнить finally блоки и закрыть браузер
+async fn cancel_automation<F>(run_key: u64, automation: std::pin::Pin<Box<F>>)
+where
+    F: std::future::Future<Output = Result<Value>>,
+{
+    let cancel = run_python(move |py| {
+        cancellable_module(py)?.getattr("cancel")?.call1((run_key,))?.is_true()
+    });
+    match cancel.await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(e) => {
+            warn!("Не удалось отменить корутину автоматизации: {}", e);
+            return;
+        }
+    }
+
+    match timeout(CANCEL_GRACE_PERIOD, automation).await {
+        Ok(_) => info!("Корутина автоматизации отменена"),
+        Err(_) => warn!("Корутина автоматизации не завершилась за {:?} после отмены", CANCEL_GRACE_PERIOD),
+    }
+}
+
+/// Вызывает необязательный `action.shutdown()` (функция или корутина), чтобы автоматизация
+/// закрыла Telethon сессии и браузеры до завершения процесса. Хук ограничен
+/// `SHUTDOWN_HOOK_TIMEOUT`. Возвращает false, если модуль action еще не загружен (автоматизация
+/// не запускалась) или в нем нет `shutdown`
+pub async fn run_shutdown_hook() -> Result<bool> {
+    let hook = run_python(|py| {
+        let hook = py.import("sys")?
+            .getattr("modules")?
+            .call_method1("get", ("action",))?;
+        if hook.is_none() || !hook.hasattr("shutdown")? {
+            return Ok(false);
+        }
+        cancellable_module(py)?
+            .getattr("run_shutdown_hook")?
+            .call1((hook.getattr("shutdown")?, SHUTDOWN_HOOK_TIMEOUT.as_secs_f64()))?;
+        Ok(true)
+    });
+
+    // Запас сверх таймаута хука на захват GIL и планирование в loop
+    match timeout(SHUTDOWN_HOOK_TIMEOUT + Duration::from_secs(1), hook).await {
+        Ok(called) => called,
+        Err(_) => Err(anyhow!("action.shutdown() не завершился за {:?}", SHUTDOWN_HOOK_TIMEOUT)),
+    }
+}
+
+/// Таймаут автоматизации устройства или общий, если устройство его не переопределяет.
+/// `None` (в том числе нулевой таймаут устройства) - без таймаута
+pub async fn automation_timeout(device_id: Option<&str>, default_timeout: Option<Duration>) -> Option<Duration> {
+    let device_timeout = match device_id {
+        Some(device_id) => get_device_automation_timeout(device_id).await.ok().flatten(),
+        None => None,
+    };
+    match device_timeout {
+        Some(device_timeout) => (!device_timeout.is_zero()).then_some(device_timeout),
+        None => default_timeout,
+    }
+}
+
+/// Запускает автоматизацию до `attempts` раз. Попытка неуспешна, если Python выбросил
+/// исключение или возвращенное значение не прошло `predicate` (секция [success] конфига).
+/// `PrecheckFailed` возвращается сразу, без повторов и без учета в счетчиках попыток.
+/// Возвращает результат последней попытки
+pub async fn run_automation_with_retry(
+    kwargs: &AutomationKwargs,
+    predicate: Option<&SuccessPredicate>,
+    attempts: u32,
+    default_timeout: Option<Duration>,
+    system: &Arc<SystemConfig>,
+) -> RunOutcome {
+    run_with_retry_cancellable(kwargs, predicate, attempts, default_timeout, system, &CancellationToken::new()).await
+}
+
+/// `run_automation_with_retry` с отменой через `cancel`: отмененная попытка не повторяется
+async fn run_with_retry_cancellable(
+    kwargs: &AutomationKwargs,
+    predicate: Option<&SuccessPredicate>,
+    attempts: u32,
+    default_timeout: Option<Duration>,
+    system: &Arc<SystemConfig>,
+    cancel: &CancellationToken,
+) -> RunOutcome {
+    let attempts = attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        let mut outcome = run_automation_cancellable(kwargs, default_timeout, system, cancel).await;
+        match (outcome.status, &outcome.data) {
+            (RunStatus::Success, Some(value)) if predicate.is_some_and(|predicate| !predicate.check(value)) => {
+                warn!("Попытка {}/{}: результат {} не удовлетворяет условию успеха", attempt, attempts, value);
+                outcome.error = Some(format!("Результат автоматизации не удовлетворяет условию успеха: {}", value));
+                outcome.status = RunStatus::Failed;
+            }
+            (RunStatus::Success | RunStatus::Cancelled, _) => {}
+            // Повтор не поможет неготовому окружению, а попытки автоматизации не было
+            (RunStatus::PrecheckFailed, _) => return outcome,
+            (status, _) => warn!("Попытка {}/{} завершилась со статусом {:?}", attempt, attempts, status),
+        }
+
+        system.record_automation_run(matches!(outcome.status, RunStatus::Failed | RunStatus::TimedOut));
+        if matches!(outcome.status, RunStatus::Success | RunStatus::Cancelled) {
+            return outcome;
+        }
+
+        if attempt >= attempts {
+            return outcome;
+        }
+        attempt += 1;
+    }
+}
+
+/// Количество попыток автоматизации из AUTOMATION_ATTEMPTS (по умолчанию 1 - без повторов)
+pub fn automation_attempts() -> u32 {
+    std::env::var("AUTOMATION_ATTEMPTS")
+        .ok()
+        .and_then(|value| value.trim().parse::<u32>().ok())
+        .unwrap_or(1)
+}
+
+/// Поведение `run_fleet` при ошибке одного из устройств (FLEET_MODE)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FleetMode {
+    /// Каждое устройство запускается независимо, собираются все результаты (по умолчанию)
+    #[default]
+    BestEffort,
+    /// Первая ошибка отменяет остальные устройства - для CI, где важен только факт падения
+    FailFast,
+}
+
+impl FleetMode {
+    /// Читает FLEET_MODE (`best-effort` | `fail-fast`), по умолчанию `best-effort`
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("FLEET_MODE") {
+            Ok(value) => value.parse(),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+impl std::str::FromStr for FleetMode {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "best-effort" => Ok(Self::BestEffort),
+            "fail-fast" => Ok(Self::FailFast),
+            other => Err(anyhow!(
+                "Некорректный FLEET_MODE '{}' (допустимо: best-effort, fail-fast)",
+                other
+            )),
+        }
+    }
+}
+
+/// Запускает автоматизацию параллельно на нескольких устройствах (device_id -> kwargs устройства),
+/// каждое в своей задаче tokio (Python выполняется в blocking потоках), и возвращает результат
+/// каждого в исходном порядке. В режиме `FailFast` первая ошибка через `CancellationToken`
+/// отменяет корутины остальных устройств (`cancel_automation`), они получают статус `Cancelled`
+pub async fn run_fleet(
+    fleet: Vec<(String, AutomationKwargs)>,
+    mode: FleetMode,
+    predicate: Option<&SuccessPredicate>,
+    attempts: u32,
+    default_timeout: Option<Duration>,
+    system: &Arc<SystemConfig>,
+) -> Vec<(String, RunOutcome)> {
+    info!("Запуск парка из {} устройств ({:?})", fleet.len(), mode);
+    let cancel = CancellationToken::new();
+    let predicate = predicate.cloned();
+    let device_ids: Vec<String> = fleet.iter().map(|(device_id, _)| device_id.clone()).collect();
+
+    let runs = fleet.into_iter().map(|(device_id, kwargs)| {
+        let cancel = cancel.clone();
+        let predicate = predicate.clone();
+        let system = Arc::clone(system);
+        tokio::spawn(async move {
+            let outcome = run_with_retry_cancellable(
+                &kwargs,
+                predicate.as_ref(),
+                attempts,
+                default_timeout,
+                &system,
+                &cancel,
+            ).await;
+
+            if !outcome.is_success() && outcome.status != RunStatus::Cancelled
+                && mode == FleetMode::FailFast && !cancel.is_cancelled()
+            {
+                error!(
+                    "Устройство {} завершилось со статусом {:?}, отмена остальных (fail-fast)",
+                    device_id,
+                    outcome.status
+                );
+                cancel.cancel();
+            }
+            outcome
         })
-    }).await {
-        Ok(result) => match result {
-            Ok(_) => {
-                info!("Автоматизация успешно завершена");
-                Ok(())
-            },
-            Err(e) => {
-                error!("Ошибка при выполнении автоматизации: {:?}", e);
-                Err(anyhow!("Python error: {:?}", e))
+    });
+
+    let results: Vec<(String, RunOutcome)> = device_ids
+        .into_iter()
+        .zip(join_all(runs).await)
+        .map(|(device_id, joined)| {
+            let outcome = joined.unwrap_or_else(|e| {
+                error!("Задача устройства {} завершилась аварийно: {}", device_id, e);
+                RunOutcome::failed(Duration::ZERO, &anyhow!("Задача устройства завершилась аварийно: {}", e))
+            });
+            (device_id, outcome)
+        })
+        .collect();
+    let failed = results.iter().filter(|(_, outcome)| !outcome.is_success()).count();
+    info!("Парк завершен: успешно {}, с ошибкой {}", results.len() - failed, failed);
+    results
+}
+
+// Размер буфера канала для потоковой автоматизации
+const STREAM_CHANNEL_CAPACITY: usize = 64;
+
+/// Запускает потоковую автоматизацию: `entrypoint` из модуля action должен быть async генератором.
+/// Каждый yield сериализуется в JSON и отправляется в канал по мере появления.
+/// JoinHandle завершается с ошибкой, если генератор выбросил исключение.
+/// Если получатель закрыт, генератор закрывается через `aclose()`
+pub fn run_automation_streaming(
+    entrypoint: &str,
+    kwargs: &AutomationKwargs,
+) -> (mpsc::Receiver<Value>, JoinHandle<Result<()>>) {
+    let (tx, rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+    let entrypoint = entrypoint.to_string();
+    let kwargs = kwargs.clone();
+
+    let handle = tokio::task::spawn_blocking(move || {
+        info!("Запуск потоковой автоматизации через action.{}", entrypoint);
+
+        // Создаем собственный event loop и генератор
+        let (event_loop, generator) = with_python(|py| -> PyResult<(PyObject, PyObject)> {
+            let event_loop = py.import("asyncio")?.getattr("new_event_loop")?.call0()?;
+
+            let kwargs = kwargs_to_py(py, &kwargs)?;
+            let generator = py.import("action")?
+                .getattr(entrypoint.as_str())?
+                .call((), Some(kwargs))?;
+
+            Ok((event_loop.into(), generator.into()))
+        })?;
+
+        let result = drive_generator(&event_loop, &generator, &tx);
+
+        Python::with_gil(|py| {
+            if let Err(e) = event_loop.as_ref(py).call_method0("close") {
+                error!("Ошибка закрытия event loop: {:?}", e);
             }
-        },
-        Err(_) => {
-            error!("Таймаут выполнения автоматизации");
-            Err(anyhow!("Таймаут автоматизации"))
+        });
+
+        result
+    });
+
+    (rx, handle)
+}
+
+/// Прокручивает `__anext__` генератора через event loop, отпуская GIL между шагами
+fn drive_generator(event_loop: &PyObject, generator: &PyObject, tx: &mpsc::Sender<Value>) -> Result<()> {
+    loop {
+        let step = with_python(|py| -> PyResult<Option<String>> {
+            let awaitable = generator.as_ref(py).call_method0("__anext__")?;
+            match event_loop.as_ref(py).call_method1("run_until_complete", (awaitable,)) {
+                Ok(item) => {
+                    let serialized: String = py.import("json")?
+                        .getattr("dumps")?
+                        .call1((item,))?
+                        .extract()?;
+                    Ok(Some(serialized))
+                },
+                Err(e) if e.is_instance_of::<PyStopAsyncIteration>(py) => Ok(None),
+                Err(e) => Err(e),
+            }
+        })?;
+
+        let Some(serialized) = step else {
+            info!("Потоковая автоматизация завершена");
+            return Ok(());
+        };
+
+        let item: Value = serde_json::from_str(&serialized)?;
+        if tx.blocking_send(item).is_err() {
+            info!("Получатель потока закрыт, останавливаем генератор");
+            with_python(|py| {
+                let close = generator.as_ref(py).call_method0("aclose")?;
+                event_loop.as_ref(py).call_method1("run_until_complete", (close,))?;
+                Ok(())
+            })?;
+            return Ok(());
         }
     }
-}
\ No newline at end of file
+}
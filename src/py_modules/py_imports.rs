@@ -1,5 +1,12 @@
 use std::collections::HashMap;
-use once_cell::sync::Lazy;
+use std::fs;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use log::info;
+use once_cell::sync::{Lazy, OnceCell};
+
+// Файл пользовательских имен импорта по умолчанию, переопределяется через IMPORT_OVERRIDES
+const DEFAULT_IMPORT_OVERRIDES_PATH: &str = "import_overrides.toml";
 
 /// Карта специальных случаев импорта пакетов
 static PACKAGE_IMPORT_EXCEPTIONS: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
@@ -18,10 +25,39 @@ static PACKAGE_IMPORT_EXCEPTIONS: Lazy<HashMap<&str, &str>> = Lazy::new(|| {
     m
 });
 
-/// Получает корректное имя пакета для импорта
+/// Пользовательские имена импорта из `import_overrides.toml`, важнее встроенных
+static IMPORT_OVERRIDES: OnceCell<HashMap<String, String>> = OnceCell::new();
+
+/// Загружает пользовательские имена импорта (`пакет = "имя_импорта"`) из IMPORT_OVERRIDES
+/// или `import_overrides.toml`. Отсутствующий файл - не ошибка. Вызывается один раз при запуске,
+/// до проверки импортов; повторный вызов оставляет уже загруженную карту
+pub fn load_import_overrides() -> Result<()> {
+    let path = std::env::var("IMPORT_OVERRIDES")
+        .ok()
+        .filter(|path| !path.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_IMPORT_OVERRIDES_PATH.to_string());
+    let path = Path::new(path.trim());
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Не удалось прочитать {}: {}", path.display(), e))?;
+    let overrides: HashMap<String, String> = toml::from_str(&content)
+        .map_err(|e| anyhow!("Ошибка разбора {} (ожидается пакет = \"имя_импорта\"): {}", path.display(), e))?;
+
+    info!("Загружено имен импорта: {} из {}", overrides.len(), path.display());
+    let _ = IMPORT_OVERRIDES.set(overrides);
+    Ok(())
+}
+
+/// Получает корректное имя пакета для импорта: пользовательское, встроенное или само имя пакета
 pub fn get_import_name(package: &str) -> &str {
+    if let Some(import_name) = IMPORT_OVERRIDES.get().and_then(|overrides| overrides.get(package)) {
+        return import_name;
+    }
     PACKAGE_IMPORT_EXCEPTIONS
         .get(package)
         .copied()
         .unwrap_or(package)
-}
\ No newline at end of file
+}
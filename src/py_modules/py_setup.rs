@@ -1,51 +1,198 @@
 use std::env;
-use std::path::PathBuf;
-use std::process::Command;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
 use anyhow::{Result, anyhow};
-use log::{info, error, debug};
+use log::{info, error, debug, warn};
 use pyo3::Python;
-use glob::glob;
+use pyo3::types::IntoPyDict;
+use crate::config::{PlaywrightBrowser, PythonConfig};
+use crate::emulation::browser_executable_from_env;
+use crate::py_modules::py_imports::get_import_name;
+use crate::utils::{
+    import_all_with_timeout, import_timeout, import_with_timeout, parse_requirements, parse_requirements_file, requirements_hash, truncate_log_lines, with_python,
+    ImportTimeout,
+};
+
+/// Чье окружение Python использует бот
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PythonEnvironment {
+    /// `python_env` в текущей директории, создается ботом при отсутствии
+    Managed,
+    /// Готовое окружение пользователя (venv, conda, poetry) из `venv_path`
+    External,
+    /// Системный интерпретатор (`use_system`)
+    System,
+}
 
 pub struct PythonSetup {
     venv_path: PathBuf,
     requirements_path: PathBuf,
+    python: PathBuf,
+    environment: PythonEnvironment,
+    settings: PythonConfig,
+    /// Кэш браузеров Playwright, определяется один раз при создании
+    playwright_cache: PathBuf,
 }
 
 impl PythonSetup {
+    /// Окружение только по переменным окружения (без секции [python])
     pub fn new() -> Result<Self> {
-        let current_dir = env::current_dir()?;
-        Ok(Self {
-            venv_path: current_dir.join("python_env"),
-            requirements_path: current_dir.join("requirements.txt"),
-        })
+        Self::from_config(&PythonConfig::default())
+    }
+
+    /// Окружение по секции [python] (с откатом на переменные окружения): `use_system` -
+    /// системный Python, `venv_path` - существующее окружение, иначе `python_env` в текущей
+    /// директории. Остальные поля секции управляют установкой зависимостей
+    pub fn from_config(config: &PythonConfig) -> Result<Self> {
+        let setup = if config.use_system() {
+            Self::system()?
+        } else if let Some(venv_path) = config.venv_path() {
+            Self::with_venv(venv_path)?
+        } else {
+            let venv_path = env::current_dir()?.join("python_env");
+            Self {
+                python: Self::venv_python(&venv_path),
+                venv_path,
+                requirements_path: Self::default_requirements_path()?,
+                environment: PythonEnvironment::Managed,
+                settings: PythonConfig::default(),
+                playwright_cache: PathBuf::new(),
+            }
+        };
+        setup.with_settings(config.clone())
+    }
+
+    /// Применяет настройки и определяет кэш браузеров Playwright:
+    /// PLAYWRIGHT_BROWSERS_PATH > `[python] playwright_browsers_path` > `<state_dir>/playwright-cache`
+    fn with_settings(mut self, settings: PythonConfig) -> Result<Self> {
+        self.playwright_cache = match settings.playwright_browsers_path() {
+            Some(path) if path.is_absolute() => path,
+            Some(path) => env::current_dir()?.join(path),
+            None => self.state_dir()?.join("playwright-cache"),
+        };
+        self.settings = settings;
+        Ok(self)
+    }
+
+    /// Существующее окружение (venv, conda, poetry): не создается и не пересоздается,
+    /// зависимости и модули проверяются как обычно
+    pub fn with_venv(venv_path: PathBuf) -> Result<Self> {
+        let python = Self::venv_python(&venv_path);
+        if !python.is_file() {
+            return Err(anyhow!(
+                "В окружении {} нет интерпретатора {}",
+                venv_path.display(),
+                if cfg!(windows) { "Scripts\\python.exe" } else { "bin/python" }
+            ));
+        }
+        Self {
+            venv_path,
+            requirements_path: Self::default_requirements_path()?,
+            python,
+            environment: PythonEnvironment::External,
+            settings: PythonConfig::default(),
+            playwright_cache: PathBuf::new(),
+        }
+        .with_settings(PythonConfig::default())
+    }
+
+    /// Системный интерпретатор (`python3`, на Windows `python`). Окружением считается его `sys.prefix`
+    pub fn system() -> Result<Self> {
+        let python_cmd = if cfg!(windows) { "python" } else { "python3" };
+        let output = Command::new(python_cmd)
+            .args(["-c", "import sys; print(sys.executable); print(sys.prefix)"])
+            .output()
+            .map_err(|e| anyhow!("Системный Python ({}) не найден: {}", python_cmd, e))?;
+        if !output.status.success() {
+            return Err(anyhow!("Не удалось запустить системный Python ({})", python_cmd));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines().map(str::trim);
+        let (Some(python), Some(prefix)) = (lines.next(), lines.next()) else {
+            return Err(anyhow!("Неожиданный вывод системного Python: {:?}", stdout.trim()));
+        };
+        Self {
+            venv_path: PathBuf::from(prefix),
+            requirements_path: Self::default_requirements_path()?,
+            python: PathBuf::from(python),
+            environment: PythonEnvironment::System,
+            settings: PythonConfig::default(),
+            playwright_cache: PathBuf::new(),
+        }
+        .with_settings(PythonConfig::default())
+    }
+
+    fn default_requirements_path() -> Result<PathBuf> {
+        Ok(env::current_dir()?.join("requirements.txt"))
+    }
+
+    /// Интерпретатор в раскладке venv (`Scripts\python.exe` на Windows, `bin/python` на остальных).
+    /// Единственное место, где зашита раскладка venv
+    fn venv_python(venv_path: &Path) -> PathBuf {
+        if cfg!(windows) {
+            venv_path.join("Scripts").join("python.exe")
+        } else {
+            venv_path.join("bin").join("python")
+        }
+    }
+
+    /// Директория исполняемых файлов интерпретатора
+    pub fn bin_dir(&self) -> PathBuf {
+        self.python.parent().map(Path::to_path_buf).unwrap_or_default()
+    }
+
+    pub fn python_executable(&self) -> PathBuf {
+        self.python.clone()
+    }
+
+    /// Единственный кэш браузеров Playwright: установка, проверка и запуск используют только его
+    pub fn playwright_cache(&self) -> &Path {
+        &self.playwright_cache
+    }
+
+    /// `python -m pip` выбранного интерпретатора: у системного Python pip может лежать
+    /// не рядом с интерпретатором или называться pip3, поэтому отдельного пути к pip нет
+    pub fn pip_command(&self) -> Command {
+        let mut command = Command::new(&self.python);
+        command.args(["-m", "pip"]);
+        command
+    }
+
+    /// Директория собственных файлов бота (хэш requirements, кэш Playwright). Для системного
+    /// Python это `target`, так как `sys.prefix` обычно недоступен на запись
+    fn state_dir(&self) -> Result<PathBuf> {
+        match self.environment {
+            PythonEnvironment::System => Ok(env::current_dir()?.join("target")),
+            PythonEnvironment::Managed | PythonEnvironment::External => Ok(self.venv_path.clone()),
+        }
     }
 
     pub fn ensure_environment(&self) -> Result<()> {
         info!("Проверка Python окружения...");
 
-        // Создаем виртуальное окружение, если его нет
-        if !self.venv_path.exists() {
-            self.create_virtual_environment()?;
+        // Создаем виртуальное окружение, если его нет; чужие окружения не создаются
+        match self.environment {
+            PythonEnvironment::Managed if !self.venv_path.exists() => self.create_virtual_environment()?,
+            PythonEnvironment::Managed => {}
+            PythonEnvironment::External => info!("Используется окружение Python: {}", self.venv_path.display()),
+            PythonEnvironment::System => info!("Используется системный Python: {}", self.python.display()),
         }
 
-        // Настраиваем путь для кэша Playwright
-        let playwright_cache = env::current_dir()?.join("target").join("playwright-cache");
-        env::set_var("PLAYWRIGHT_BROWSERS_PATH", playwright_cache.to_str().unwrap());
+        // Кэш Playwright: через PLAYWRIGHT_BROWSERS_PATH его видят Python код и очистка при остановке.
+        // Директория создается только при установке браузеров (setup_playwright)
+        env::set_var("PLAYWRIGHT_BROWSERS_PATH", &self.playwright_cache);
+        info!("Кэш браузеров Playwright: {}", self.playwright_cache.display());
 
-        // Добавляем окружение Python
-        Python::with_gil(|py| {
-            let sys = py.import("sys")?;
-            let current_dir = std::env::current_dir()?;
-            let python_path = current_dir.join("src").join("python");
-            sys.getattr("path")?.call_method1("append", (python_path.to_str(),))?;
-            Ok::<_, anyhow::Error>(())
-        })?;
-        
         // Настраиваем пути Python
+        self.add_python_sources()?;
         self.setup_python_paths()?;
         
         // Проверяем и устанавливаем зависимости
-        self.install_dependencies()?;
+        self.install_dependencies(self.settings.force_reinstall())?;
 
         // Устанавливаем браузеры Playwright
         self.setup_playwright()?;
@@ -56,6 +203,42 @@ impl PythonSetup {
         Ok(())
     }
 
+    /// Проверка окружения без изменений (режим verify-only): venv не создается, pip и
+    /// `playwright install` не запускаются, кэш Playwright не создается. Отсутствующий
+    /// интерпретатор или браузер - ошибка. Импорты проверяет `verify_imports_only`
+    pub fn check_environment(&self) -> Result<()> {
+        info!("Проверка Python окружения без установки...");
+
+        let python = self.python_executable();
+        if !python.exists() {
+            return Err(anyhow!("Интерпретатор Python не найден: {}", python.display()));
+        }
+
+        env::set_var("PLAYWRIGHT_BROWSERS_PATH", &self.playwright_cache);
+        let missing = self.missing_browsers()?;
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Браузеры Playwright не установлены в {}: {}",
+                self.playwright_cache.display(),
+                missing.join(", ")
+            ));
+        }
+
+        self.add_python_sources()?;
+        self.setup_python_paths()
+    }
+
+    /// Добавляет `src/python` в sys.path встроенного интерпретатора
+    fn add_python_sources(&self) -> Result<()> {
+        let python_path = env::current_dir()?.join("src").join("python");
+        with_python(|py| {
+            py.import("sys")?
+                .getattr("path")?
+                .call_method1("append", (python_path.to_str(),))?;
+            Ok(())
+        })
+    }
+
     fn create_virtual_environment(&self) -> Result<()> {
         info!("Создание виртуального окружения Python...");
         
@@ -71,11 +254,11 @@ impl PythonSetup {
             return Err(anyhow!("Python3 не установлен"));
         }
 
-        debug!("Используется Python: {}", String::from_utf8_lossy(&version_output.stdout));
+        debug!("Используется Python: {}", truncate_log_lines(&String::from_utf8_lossy(&version_output.stdout)));
 
         // Создаем виртуальное окружение
         let status = Command::new(python_cmd)
-            .args(&["-m", "venv", self.venv_path.to_str().unwrap()])
+            .args(["-m", "venv", self.venv_path.to_str().unwrap()])
             .status()?;
 
         if !status.success() {
@@ -85,65 +268,67 @@ impl PythonSetup {
         info!("Виртуальное окружение успешно создано");
 
         // Обновляем pip в виртуальном окружении
-        let pip_path = if cfg!(windows) {
-            self.venv_path.join("Scripts").join("pip.exe")
-        } else {
-            self.venv_path.join("bin").join("pip")
-        };
+        run_install_command("обновление pip", self.settings.install_retries(), || {
+            let mut command = self.pip_command();
+            command.args(["install", "--upgrade", "pip"]);
+            command
+        })
+        .map_err(|e| anyhow!("Не удалось обновить pip: {}", e))?;
 
-        let status = Command::new(pip_path)
-            .args(&["install", "--upgrade", "pip"])
-            .status()?;
+        info!("Pip успешно обновлен");
+        Ok(())
+    }
 
-        if !status.success() {
-            return Err(anyhow!("Не удалось обновить pip"));
+    /// Браузеры Playwright, которых нет в кэше. Уже скачанные браузеры используются повторно,
+    /// а с собственным бинарником браузера (BROWSER_EXECUTABLE) Chromium не нужен
+    fn missing_browsers(&self) -> Result<Vec<&'static str>> {
+        let mut browsers = self.settings.playwright_browsers()?;
+        if let Some(executable) = browser_executable_from_env()? {
+            info!("Используется браузер {}, Chromium Playwright не требуется", executable.display());
+            browsers.retain(|browser| *browser != PlaywrightBrowser::Chromium);
         }
 
-        info!("Pip успешно обновлен");
-        Ok(())
+        // Проверяем наличие каждого браузера сканированием директории кэша
+        Ok(browsers
+            .iter()
+            .map(PlaywrightBrowser::as_str)
+            .filter(|browser| !Self::browser_installed(&self.playwright_cache, browser))
+            .collect())
     }
 
     fn setup_playwright(&self) -> Result<()> {
         info!("Установка браузеров Playwright...");
-        
-        let python_path = if cfg!(windows) {
-            self.venv_path.join("Scripts").join("python.exe")
-        } else {
-            self.venv_path.join("bin").join("python")
-        };
-
-        // Настраиваем путь для кэша Playwright внутри виртуального окружения
-        let playwright_cache = self.venv_path.join("playwright-cache");
-        env::set_var("PLAYWRIGHT_BROWSERS_PATH", playwright_cache.to_str().unwrap());
 
-        // Проверяем наличие браузеров через glob
-        let browser_pattern = playwright_cache.join("chromium-*");
-        let browser_exists = glob(browser_pattern.to_str().unwrap())?
-            .next()
-            .is_some();
+        let python_path = self.python_executable();
+        let playwright_cache = &self.playwright_cache;
+        let missing = self.missing_browsers()?;
 
-        if !browser_exists {
-            info!("Браузеры Playwright не найдены, выполняем установку...");
+        if !missing.is_empty() {
+            info!("Браузеры Playwright не найдены ({}), выполняем установку...", missing.join(", "));
+            fs::create_dir_all(playwright_cache)
+                .map_err(|e| anyhow!("Не удалось создать кэш Playwright {}: {}", playwright_cache.display(), e))?;
             
             // Устанавливаем браузеры через playwright install
-            let status = Command::new(&python_path)
-                .args(&["-m", "playwright", "install", "chromium"])
-                .env("PLAYWRIGHT_BROWSERS_PATH", playwright_cache.to_str().unwrap())
-                .status()?;
-
-            if !status.success() {
-                return Err(anyhow!("Ошибка установки браузеров Playwright"));
-            }
+            run_install_command("playwright install", self.settings.install_retries(), || {
+                let mut command = Command::new(&python_path);
+                command
+                    .args(["-m", "playwright", "install"])
+                    .args(&missing)
+                    .env("PLAYWRIGHT_BROWSERS_PATH", playwright_cache);
+                command
+            })
+            .map_err(|e| anyhow!("Ошибка установки браузеров Playwright: {}", e))?;
 
             // Устанавливаем зависимости системы для браузеров
-            let status = Command::new(&python_path)
-                .args(&["-m", "playwright", "install-deps", "chromium"])
-                .env("PLAYWRIGHT_BROWSERS_PATH", playwright_cache.to_str().unwrap())
-                .status()?;
-
-            if !status.success() {
-                return Err(anyhow!("Ошибка установки зависимостей браузеров"));
-            }
+            run_install_command("playwright install-deps", self.settings.install_retries(), || {
+                let mut command = Command::new(&python_path);
+                command
+                    .args(["-m", "playwright", "install-deps"])
+                    .args(&missing)
+                    .env("PLAYWRIGHT_BROWSERS_PATH", playwright_cache);
+                command
+            })
+            .map_err(|e| anyhow!("Ошибка установки зависимостей браузеров: {}", e))?;
 
             info!("Браузеры Playwright успешно установлены");
         } else {
@@ -153,26 +338,151 @@ impl PythonSetup {
         Ok(())
     }
 
+    /// Проверяет наличие директории браузера (`<browser>-<revision>`) в кэше Playwright.
+    /// Используется прямое сканирование вместо glob, так как на Windows пути с
+    /// пробелами и обратными слешами ломают glob-паттерн.
+    fn browser_installed(cache_dir: &Path, browser: &str) -> bool {
+        let prefix = format!("{}-", browser);
+        let entries = match fs::read_dir(cache_dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug!("Кэш Playwright недоступен ({}): {}", cache_dir.display(), e);
+                return false;
+            }
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+    }
+
+    /// Отчет о версиях окружения для `bombie-bot --version`: версия крейта, Python из venv,
+    /// Playwright и установленные браузеры. Не создает venv, отсутствие компонентов отмечается в отчете
+    pub fn version_report(&self) -> String {
+        let mut report = format!("bombie-bot {}\n", env!("CARGO_PKG_VERSION"));
+
+        if !self.venv_path.exists() {
+            report.push_str(&format!("Python: виртуальное окружение не найдено ({})\n", self.venv_path.display()));
+            return report;
+        }
+
+        match self.get_python_version() {
+            Ok(version) => report.push_str(&format!("Python: {} ({})\n", version, self.venv_path.display())),
+            Err(e) => report.push_str(&format!("Python: не определен ({})\n", e)),
+        }
+
+        let python_path = self.python_executable();
+
+        let playwright_version = Command::new(&python_path)
+            .args(["-m", "playwright", "--version"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| truncate_log_lines(String::from_utf8_lossy(&output.stdout).trim().trim_start_matches("Version ")));
+        match playwright_version {
+            Some(version) => report.push_str(&format!("Playwright: {}\n", version)),
+            None => report.push_str("Playwright: не установлен\n"),
+        }
+
+        let browsers = Self::installed_browsers(&self.playwright_cache);
+        if browsers.is_empty() {
+            report.push_str(&format!("Браузеры: не установлены ({})\n", self.playwright_cache.display()));
+        } else {
+            report.push_str(&format!("Браузеры: {}\n", browsers.join(", ")));
+        }
+
+        report
+    }
+
+    /// Диагностический отчет для `bombie-bot doctor`: итоговый sys.path, PYTHONPATH, VIRTUAL_ENV,
+    /// версии установленных пакетов и статус импорта каждой зависимости из requirements.txt.
+    /// Вызывать после `ensure_environment`
+    pub fn doctor_report(&self) -> Result<String> {
+        let mut report = self.version_report();
+
+        let env_value = |name: &str| env::var(name).unwrap_or_else(|_| "<не задан>".to_string());
+        report.push_str(&format!("\nVIRTUAL_ENV: {}\n", env_value("VIRTUAL_ENV")));
+        report.push_str(&format!("PYTHONPATH: {}\n", env_value("PYTHONPATH")));
+
+        let paths: Vec<String> = with_python(|py| py.import("sys")?.getattr("path")?.extract())?;
+        report.push_str("\nsys.path:\n");
+        for path in &paths {
+            report.push_str(&format!("  {}\n", path));
+        }
+
+        let packages = parse_requirements()?;
+        let timeout = import_timeout();
+        report.push_str("\nЗависимости:\n");
+        for requirement in &packages {
+            let package = &requirement.name;
+            if !requirement.is_importable() {
+                report.push_str(&format!("  {} ({}) - импорт не проверяется\n", package, requirement.spec()));
+                continue;
+            }
+            let version = with_python(|py| {
+                let metadata = py.import("importlib.metadata")?;
+                Ok(metadata
+                    .call_method1("version", (package.as_str(),))
+                    .and_then(|version| version.extract::<String>())
+                    .ok())
+            })?;
+            let version = version.unwrap_or_else(|| "не установлен".to_string());
+            let status = match import_with_timeout(package, timeout) {
+                Ok(()) => "OK".to_string(),
+                Err(e) => format!("ОШИБКА: {}", truncate_log_lines(&e.to_string())),
+            };
+            report.push_str(&format!("  {} {} - {}\n", package, version, status));
+        }
+
+        Ok(report)
+    }
+
+    /// Имена директорий браузеров в кэше Playwright (например "chromium-1091")
+    fn installed_browsers(cache_dir: &Path) -> Vec<String> {
+        let mut browsers: Vec<String> = fs::read_dir(cache_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .filter(|entry| entry.path().is_dir())
+                    .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                    .filter(|name| ["chromium", "firefox", "webkit"].iter().any(|browser| name.starts_with(browser)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        browsers.sort();
+        browsers
+    }
+
+    /// Директория установки пакетов (`sysconfig` purelib) выбранного интерпретатора
+    fn site_packages(&self) -> Result<PathBuf> {
+        let output = Command::new(&self.python)
+            .args(["-c", "import sysconfig; print(sysconfig.get_paths()['purelib'])"])
+            .output()?;
+        if !output.status.success() {
+            return Err(anyhow!("Не удалось определить site-packages для {}", self.python.display()));
+        }
+        Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+    }
+
     fn setup_python_paths(&self) -> Result<()> {
         // Определяем версию Python динамически
         let python_version = self.get_python_version()?;
         info!("Обнаружена версия Python: {}", python_version);
         
         // Корректное определение путей с учетом Windows
-        let venv_site_packages = if cfg!(windows) {
-            self.venv_path.join("Lib").join("site-packages")
-        } else {
-            self.venv_path
+        let venv_site_packages = match self.environment {
+            PythonEnvironment::Managed if cfg!(windows) => self.venv_path.join("Lib").join("site-packages"),
+            PythonEnvironment::Managed => self.venv_path
                 .join("lib")
                 .join(format!("python{}", python_version))
-                .join("site-packages")
+                .join("site-packages"),
+            // Раскладка conda и системного Python отличается от venv (dist-packages и т.п.),
+            // поэтому путь спрашиваем у самого интерпретатора
+            PythonEnvironment::External | PythonEnvironment::System => self.site_packages()?,
         };
 
-        let venv_bin = if cfg!(windows) {
-            self.venv_path.join("Scripts")
-        } else {
-            self.venv_path.join("bin")
-        };
+        let venv_bin = self.bin_dir();
 
         // Проверка существования директории с корректной обработкой ошибок для Windows
         if !venv_site_packages.exists() {
@@ -193,11 +503,13 @@ impl PythonSetup {
         let pythonpath = venv_site_packages.to_str().ok_or_else(|| 
             anyhow!("Невалидный путь site-packages")
         )?;
-        env::set_var("PYTHONPATH", &pythonpath);
+        env::set_var("PYTHONPATH", pythonpath);
         info!("Установлен PYTHONPATH: {}", pythonpath);
 
-        // Настраиваем VIRTUAL_ENV
-        env::set_var("VIRTUAL_ENV", self.venv_path.to_str().unwrap());
+        // Настраиваем VIRTUAL_ENV; системный Python виртуальным окружением не является
+        if self.environment != PythonEnvironment::System {
+            env::set_var("VIRTUAL_ENV", self.venv_path.to_str().unwrap());
+        }
 
         // Проверяем настройку путей
         Python::with_gil(|py| {
@@ -229,76 +541,180 @@ impl PythonSetup {
     }
 
     fn verify_modules(&self) -> Result<()> {
-        Python::with_gil(|py| {
-            info!("Проверка импорта модулей...");
+        info!("Проверка импорта модулей...");
             
-            // Выводим текущие пути Python
-            let sys = py.import("sys")?;
-            let paths: Vec<String> = sys.getattr("path")?.extract()?;
-            info!("Пути Python перед импортом: {:?}", paths);
-            
-            // Пробуем импортировать telethon
-            match py.import("telethon") {
-                Ok(_) => {
-                    info!("Модуль telethon успешно импортирован");
-                    Ok(())
-                },
-                Err(e) => {
-                    error!("Ошибка импорта telethon: {}", e);
-                    error!("Текущая директория: {:?}", env::current_dir()?);
-                    error!("PYTHONPATH: {:?}", env::var("PYTHONPATH"));
-                    Err(anyhow!("Не удалось импортировать telethon: {}", e))
-                }
+        // Выводим текущие пути Python
+        let paths: Vec<String> = with_python(|py| py.import("sys")?.getattr("path")?.extract())?;
+        info!("Пути Python перед импортом: {:?}", paths);
+
+        // Пробуем импортировать telethon
+        match self.import_with_recovery("telethon") {
+            Ok(()) => {
+                info!("Модуль telethon успешно импортирован");
+                Ok(())
+            },
+            Err(e) => {
+                error!("Ошибка импорта telethon: {}", e);
+                error!("Текущая директория: {:?}", env::current_dir()?);
+                error!("PYTHONPATH: {:?}", env::var("PYTHONPATH"));
+                Err(anyhow!("Не удалось импортировать telethon: {}", e))
             }
-        })
+        }
     }
 
-    fn install_dependencies(&self) -> Result<()> {
-        let pip_path = if cfg!(windows) {
-            self.venv_path.join("Scripts").join("pip.exe")
-        } else {
-            self.venv_path.join("bin").join("pip")
+    /// Проверяет импорт всех пакетов одним пакетным импортом (`import_all_with_timeout`),
+    /// а неудавшиеся пакеты восстанавливает по одному через `import_with_recovery`.
+    /// Общее время проверки пишется в лог, чтобы замедление было заметно
+    pub fn verify_imports(&self, packages: &[String]) -> Result<()> {
+        let started = std::time::Instant::now();
+        let failures = import_all_with_timeout(packages, import_timeout())?;
+        for (package, e) in &failures {
+            warn!("Пакет {} не импортирован при пакетной проверке: {}", package, e);
+            self.import_with_recovery(package)
+                .map_err(|e| anyhow!("Ошибка импорта пакета {}: {}", package, e))?;
+        }
+        info!(
+            "Проверка импортов завершена: {} пакетов за {:?} (восстановлено: {})",
+            packages.len(),
+            started.elapsed(),
+            failures.len()
+        );
+        Ok(())
+    }
+
+    /// Проверка импортов без восстановления (режим verify-only): любой неимпортированный
+    /// пакет - ошибка, pip не запускается
+    pub fn verify_imports_only(&self, packages: &[String]) -> Result<()> {
+        let failures = import_all_with_timeout(packages, import_timeout())?;
+        if failures.is_empty() {
+            return Ok(());
+        }
+        let details: Vec<String> = failures
+            .iter()
+            .map(|(package, e)| format!("{}: {}", package, e))
+            .collect();
+        Err(anyhow!("Пакеты не импортируются:\n{}", details.join("\n")))
+    }
+
+    /// Импортирует пакет; при первой неудаче переустанавливает только его через
+    /// `pip install --force-reinstall` и повторяет импорт один раз.
+    /// Лечит частично установленные пакеты после прерванного pip без пересоздания venv
+    pub fn import_with_recovery(&self, package: &str) -> Result<()> {
+        let timeout = import_timeout();
+        let first_error = match import_with_timeout(package, timeout) {
+            Ok(()) => return Ok(()),
+            // Зависший импорт может держать GIL, переустановка и повтор бессмысленны
+            Err(e) if e.is::<ImportTimeout>() => return Err(e),
+            Err(e) => e,
         };
 
+        warn!("Импорт пакета {} не удался ({}), пробуем переустановить", package, first_error);
+        self.reinstall_package(package)?;
+
+        Python::with_gil(|py| Self::purge_imported_modules(py, package))?;
+        import_with_timeout(package, timeout)
+            .map_err(|e| anyhow!("Пакет {} не импортируется даже после переустановки: {}", package, e))
+    }
+
+    fn reinstall_package(&self, package: &str) -> Result<()> {
+        let spec = self.requirement_spec(package);
+        info!("Переустановка пакета: {}", spec);
+
+        run_install_command(&format!("переустановка {}", spec), self.settings.install_retries(), || {
+            let mut command = self.pip_command();
+            command.args(["install", "--force-reinstall", "--no-deps", &spec]);
+            command
+        })
+        .map_err(|e| anyhow!("Не удалось переустановить пакет {}: {}", spec, e))
+    }
+
+    /// Возвращает строку пакета из requirements.txt вместе с версией (например "telethon==1.28.5"),
+    /// чтобы переустановка не меняла закрепленную версию
+    fn requirement_spec(&self, package: &str) -> String {
+        parse_requirements_file(&self.requirements_path)
+            .ok()
+            .and_then(|requirements| requirements.into_iter().find(|requirement| requirement.name == package))
+            .map(|requirement| requirement.spec())
+            .unwrap_or_else(|| package.to_string())
+    }
+
+    /// Удаляет из sys.modules остатки неудачного импорта, чтобы повторный импорт читал файлы заново
+    fn purge_imported_modules(py: Python<'_>, package: &str) -> Result<()> {
+        let import_name = get_import_name(package);
+        let roots: Vec<String> = [import_name, package, &package.replace('-', "_")]
+            .iter()
+            .map(|name| name.split('.').next().unwrap_or(name).to_string())
+            .collect();
+
+        let locals = [("roots", roots)].into_py_dict(py);
+        py.run(
+            r#"
+import importlib, sys
+importlib.invalidate_caches()
+for name in list(sys.modules):
+    if any(name == root or name.startswith(root + ".") for root in roots):
+        del sys.modules[name]
+"#,
+            None,
+            Some(locals),
+        )?;
+        Ok(())
+    }
+
+    /// Файл с хэшем requirements последней успешной установки
+    fn requirements_hash_path(&self) -> Result<PathBuf> {
+        Ok(self.state_dir()?.join(".requirements.hash"))
+    }
+
+    /// `pip install -r requirements.txt`, если requirements (с включениями `-r`) изменились
+    /// с последней успешной установки. `force` (или FORCE_REINSTALL) запускает pip в любом случае
+    fn install_dependencies(&self, force: bool) -> Result<()> {
+        let hash_path = self.requirements_hash_path()?;
+        let requirements_hash = requirements_hash(&self.requirements_path)?;
+        if !force && fs::read_to_string(&hash_path).is_ok_and(|stored| stored.trim() == requirements_hash) {
+            info!("Зависимости не изменились с последней установки, pip пропущен");
+            return Ok(());
+        }
+
         info!("Установка зависимостей из {:?}", self.requirements_path);
         
-        let status = Command::new(&pip_path)
-            .args(&[
-                "install",
-                "-r",
-                self.requirements_path.to_str().unwrap()
-            ])
-            .status()?;
+        run_install_command("pip install -r requirements.txt", self.settings.install_retries(), || {
+            let mut command = self.pip_command();
+            command.arg("install").arg("-r").arg(&self.requirements_path);
+            command
+        })
+        .map_err(|e| anyhow!("Не удалось установить зависимости: {}", e))?;
 
-        if !status.success() {
-            return Err(anyhow!("Не удалось установить зависимости"));
+        if let Err(e) = fs::write(&hash_path, &requirements_hash) {
+            warn!("Не удалось сохранить хэш зависимостей в {:?}: {}", hash_path, e);
         }
-
         Ok(())
     }
 
     // Добавляем новый метод для определения версии Python
     fn get_python_version(&self) -> Result<String> {
-        let python_path = if cfg!(windows) {
-            self.venv_path.join("Scripts").join("python.exe")
-        } else {
-            self.venv_path.join("bin").join("python")
-        };
+        let python_path = self.python_executable();
 
         let output = Command::new(&python_path)
-            .args(&["--version"])
+            .args(["--version"])
             .output()?;
 
         if !output.status.success() {
             return Err(anyhow!("Не удалось определить версию Python"));
         }
 
-        let version_string = String::from_utf8(output.stdout)?;
+        // Вывод может быть не в UTF-8 (например CP1251 на Windows), а старые версии Python
+        // печатают версию в stderr, поэтому разбираем оба потока с заменой некорректных байт
+        let version_string = format!(
+            "{} {}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
         // Извлекаем только основную версию (например, "3.13" из "Python 3.13.0")
         let version = version_string
             .split_whitespace()
-            .nth(1)
-            .ok_or_else(|| anyhow!("Неверный формат версии Python"))?
+            .find(|token| token.starts_with(|c: char| c.is_ascii_digit()))
+            .ok_or_else(|| anyhow!("Неверный формат версии Python: {:?}", version_string.trim()))?
             .split('.')
             .take(2)
             .collect::<Vec<&str>>()
@@ -307,3 +723,80 @@ impl PythonSetup {
         Ok(version)
     }
 }
+
+// Пауза перед первым повтором; каждая следующая вдвое длиннее
+const INSTALL_RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+
+// Признаки сетевой ошибки в stderr pip и playwright. Ошибки разрешения зависимостей
+// ("No matching distribution", "ResolutionImpossible") без них повторять бессмысленно
+const NETWORK_ERROR_MARKERS: &[&str] = &[
+    "connectionerror",
+    "connection reset",
+    "connection refused",
+    "connection aborted",
+    "newconnectionerror",
+    "readtimeout",
+    "timed out",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "getaddrinfo",
+    "sslerror",
+    "proxyerror",
+    "remote end closed connection",
+    "incompleteread",
+    "econnreset",
+    "etimedout",
+    "socket hang up",
+    "download failed",
+];
+
+fn is_network_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    NETWORK_ERROR_MARKERS.iter().any(|marker| stderr.contains(marker))
+}
+
+/// Запускает команду установки; при сетевой ошибке повторяет до `retries` раз
+/// с экспоненциальной паузой. stdout выводится как обычно, stderr перехватывается для
+/// классификации ошибки и пишется в лог. Код выхода 2 (ошибка использования pip) не повторяется
+fn run_install_command(description: &str, retries: u32, build: impl Fn() -> Command) -> Result<()> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let output = build().stderr(Stdio::piped()).spawn()?.wait_with_output()?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        error!("{}: попытка {} завершилась с {}:\n{}", description, attempt, output.status, truncate_log_lines(stderr.trim()));
+
+        let retryable = output.status.code() != Some(2) && is_network_error(&stderr);
+        if !retryable || attempt > retries {
+            let reason = if retryable { "сетевая ошибка, повторы исчерпаны" } else { "ошибка не сетевая" };
+            return Err(anyhow!("{} не удалось (попыток: {}, {})", description, attempt, reason));
+        }
+
+        let delay = INSTALL_RETRY_BASE_DELAY * 2u32.saturating_pow(attempt - 1);
+        warn!("{}: сетевая ошибка, повтор {}/{} через {:?}", description, attempt, retries, delay);
+        thread::sleep(delay);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(windows)]
+    #[test]
+    fn venv_python_uses_scripts_dir_on_windows() {
+        let venv = Path::new(r"C:\bots\venv");
+        assert_eq!(PythonSetup::venv_python(venv), venv.join("Scripts").join("python.exe"));
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn venv_python_uses_bin_dir() {
+        let venv = Path::new("/opt/bombie/venv");
+        assert_eq!(PythonSetup::venv_python(venv), PathBuf::from("/opt/bombie/venv/bin/python"));
+    }
+}
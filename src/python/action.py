@@ -12,6 +12,7 @@ from telethon.tl.functions.messages import RequestWebViewRequest
 from telethon.tl.types import DataJSON
 from telethon.tl.types import InputUser
 from urllib.parse import urlparse
+from pathlib import Path
 from bot_handle import handle_webapp
 
 # Загрузка переменных окружения
@@ -198,19 +199,94 @@ class TelegramMiniAppAutomation:
             logger.error(f"Ошибка при навигации: {e}")
             return False
 
-async def initialize_automation() -> bool:
-    """Точка входа для вызова из Rust"""
+# Активные логины по event loop, чтобы shutdown() закрыл сессии своего loop
+_active_logins = {}
+
+async def shutdown():
+    """Хук остановки, вызываемый из Rust по Ctrl+C до завершения процесса.
+    Закрывает Telethon сессии запусков текущего event loop, чтобы .session файлы не повредились"""
+    loop = asyncio.get_running_loop()
+    for login in list(_active_logins.get(loop, ())):
+        try:
+            await login.cleanup()
+        except Exception as e:
+            logger.error(f"Ошибка при закрытии сессии на остановке: {e}")
+    _active_logins.pop(loop, None)
+
+async def _heartbeat_loop(heartbeat_file: str, interval: float):
+    """Периодически обновляет heartbeat файл для внешних watchdog"""
+    path = Path(heartbeat_file)
+    while True:
+        try:
+            path.touch()
+        except Exception as e:
+            logger.error(f"Ошибка обновления heartbeat файла: {e}")
+        await asyncio.sleep(interval)
+
+async def initialize_automation(
+    automation_config: dict = None,
+    heartbeat_file: str = None,
+    heartbeat_interval: float = 30,
+    device_id: str = None,
+    telegram_session: dict = None,
+    tab_concurrency: int = 1,
+    context_mode: str = "incognito",
+    user_data_dir: str = None,
+    proxy: dict = None,
+    output_dir: str = None,
+    launch_options: dict = None,
+    context_options: dict = None,
+    init_scripts: list = None,
+    permission_overrides: dict = None,
+    keep_browser_open_on_error: bool = False,
+    should_stop=None,
+) -> bool:
+    """Точка входа для вызова из Rust
+
+    automation_config - секция [automation] из bombie.toml (вложенный dict)
+    heartbeat_file - файл, который обновляется каждые heartbeat_interval секунд
+    device_id - эмулируемое устройство, выбранное через `bombie-bot run --device`
+    telegram_session - выбранная сессия из [telegram.sessions] (api_id, api_hash, phone, session_file);
+                       если не передана, учетные данные берутся из .env
+    tab_concurrency - сколько вкладок одного устройства можно вести параллельно
+    proxy - прокси устройства (server, username, password)
+    output_dir - директория артефактов запуска/устройства (<DATA_DIR>/runs/<run_id>/devices/<device_id>)
+    context_mode - "incognito" (новый контекст на запуск) или "persistent" (профиль в user_data_dir)
+    launch_options - опции chromium.launch устройства (executable_path, args, proxy)
+    context_options - опции new_context эмулируемого устройства (viewport, user_agent, заголовки, разрешения)
+    init_scripts - скрипты context.add_init_script устройства (hardwareConcurrency, deviceMemory, ...)
+    permission_overrides - разрешения в состоянии denied/prompt (имя -> состояние), применяются через CDP
+    keep_browser_open_on_error - ждать Enter перед закрытием браузера после ошибки (KEEP_BROWSER_OPEN_ON_ERROR)
+    should_stop - функция без аргументов, True после запроса остановки бота (проверяется между шагами)
+    """
     tracer = None
     automation = None
     login = None
+    heartbeat_task = None
+    automation_config = automation_config or {}
     try:
+        logger.debug(f"Параметры автоматизации: {list(automation_config.keys())}")
+        if device_id:
+            logger.info(f"Автоматизация для устройства: {device_id}")
+        logger.debug(f"Параллельных вкладок на устройство: {tab_concurrency}")
+        if heartbeat_file:
+            heartbeat_task = asyncio.create_task(_heartbeat_loop(heartbeat_file, heartbeat_interval))
+
         # Загружаем переменные окружения
         load_dotenv()
         
-        # Получаем необходимые параметры из .env
-        api_id = os.getenv("TELEGRAM_API_ID")
-        api_hash = os.getenv("TELEGRAM_API_HASH")
-        phone = os.getenv("TELEGRAM_PHONE")
+        # Получаем необходимые параметры из выбранной сессии или .env
+        session_file = None
+        if telegram_session:
+            logger.info(f"Используется Telegram сессия: {telegram_session.get('name')}")
+            api_id = telegram_session.get("api_id")
+            api_hash = telegram_session.get("api_hash")
+            phone = telegram_session.get("phone")
+            session_file = telegram_session.get("session_file")
+        else:
+            api_id = os.getenv("TELEGRAM_API_ID")
+            api_hash = os.getenv("TELEGRAM_API_HASH")
+            phone = os.getenv("TELEGRAM_PHONE")
         
         if not all([api_id, api_hash, phone]):
             logger.error("Отсутствуют необходимые переменные окружения")
@@ -221,12 +297,18 @@ async def initialize_automation() -> bool:
             login = TelegramLogin(
                 api_id=int(api_id),
                 api_hash=api_hash,
-                phone=phone
+                phone=phone,
+                session_file=session_file,
             )
+            _active_logins.setdefault(asyncio.get_running_loop(), set()).add(login)
             
             # Выполняем подключение
             success, url, device_config, bot_metadata, webapp_data = await login.connect()
             
+            if should_stop and should_stop():
+                logger.info("Остановка бота запрошена после логина, автоматизация прервана")
+                return False
+
             if success:
                 logger.info("Логин успешно выполнен")
                 if url:
@@ -250,7 +332,19 @@ async def initialize_automation() -> bool:
                         
                         try:
                             # Передаем WebView URL в handle_webapp
-                            bot_task = asyncio.create_task(handle_webapp(webapp_url))
+                            bot_task = asyncio.create_task(handle_webapp(
+                                webapp_url,
+                                tab_concurrency=tab_concurrency,
+                                context_mode=context_mode,
+                                user_data_dir=user_data_dir,
+                                proxy=proxy,
+                                output_dir=output_dir,
+                                launch_options=launch_options,
+                                context_options=context_options,
+                                init_scripts=init_scripts,
+                                permission_overrides=permission_overrides,
+                                keep_browser_open_on_error=keep_browser_open_on_error,
+                            ))
                             result = await bot_task
                             
                             if result:
@@ -335,9 +429,13 @@ async def initialize_automation() -> bool:
         return False
         
     finally:
+        if heartbeat_task:
+            heartbeat_task.cancel()
+
         # Корректное закрытие ресурсов
         try:
             if login:
+                _active_logins.get(asyncio.get_running_loop(), set()).discard(login)
                 logger.debug("Очистка ресурсов логина")
                 await login.cleanup()
         except Exception as e:
@@ -1,8 +1,10 @@
 import asyncio
+import threading
+from contextlib import asynccontextmanager
 from pathlib import Path
-from typing import Optional, Dict, Any
+from typing import Optional, Dict, Any, List
 from loguru import logger
-from playwright.async_api import async_playwright, Browser, Page, BrowserContext
+from playwright.async_api import async_playwright, Browser, Page, BrowserContext, Error as PlaywrightError
 from utils import ScreenRecorder, HumanBehavior
 from tracer import TracerManager
 from web_modules import GameCanvasHandler
@@ -20,6 +22,9 @@ ENABLE_VIDEO = os.getenv('ENABLE_VIDEO', 'false').lower() == 'true'
 ENABLE_TRACING = os.getenv('ENABLE_TRACING', 'false').lower() == 'true'
 ENABLE_LOGGING = os.getenv('ENABLE_LOGGING', 'true').lower() == 'true'
 ENABLE_HEADLESS = os.getenv('ENABLE_HEADLESS', 'false').lower() == 'true'
+# Повторы запуска браузера (падение Chromium при старте), отдельно от AUTOMATION_ATTEMPTS
+BROWSER_LAUNCH_ATTEMPTS = max(1, int(os.getenv('BROWSER_LAUNCH_ATTEMPTS', '3')))
+BROWSER_LAUNCH_RETRY_DELAY = float(os.getenv('BROWSER_LAUNCH_RETRY_DELAY', '2'))
 
 # Статические настройки
 MAX_RECONNECT_ATTEMPTS = 3
@@ -30,8 +35,41 @@ VIEWPORT_WIDTH = 412
 VIEWPORT_HEIGHT = 815
 
 class BotHandler:
-    def __init__(self, webapp_url: str):
+    def __init__(
+        self,
+        webapp_url: str,
+        tab_concurrency: int = 1,
+        context_mode: str = "incognito",
+        user_data_dir: Optional[str] = None,
+        proxy: Optional[Dict[str, str]] = None,
+        output_dir: Optional[str] = None,
+        launch_options: Optional[Dict[str, Any]] = None,
+        context_options: Optional[Dict[str, Any]] = None,
+        init_scripts: Optional[List[str]] = None,
+        permission_overrides: Optional[Dict[str, str]] = None,
+        keep_browser_open_on_error: bool = False,
+    ):
         self.webapp_url = webapp_url
+        # KEEP_BROWSER_OPEN_ON_ERROR: Rust передает True только вне парка и без таймаута автоматизации;
+        # в headless режиме смотреть не на что, поэтому ожидание отключено
+        self.keep_browser_open_on_error = keep_browser_open_on_error and not ENABLE_HEADLESS
+        # Опции chromium.launch эмулируемого устройства: executable_path (BROWSER_EXECUTABLE), args
+        self.device_launch_options = launch_options or {}
+        # Опции контекста и init scripts эмулируемого устройства; без них - get_telegram_device_config
+        self.device_context_options = context_options or {}
+        self.init_scripts = init_scripts or []
+        # Разрешения denied/prompt: Playwright умеет только выдавать, остальное - через CDP
+        self.permission_overrides = permission_overrides or {}
+        # Директория записей и трейсов этого запуска; без нее - общая ./recordings
+        self.output_dir = Path(output_dir) if output_dir else Path("./recordings")
+        # server/username/password; прокси с авторизацией задается на уровне контекста
+        self.proxy = proxy
+        # incognito - launch + new_context, persistent - launch_persistent_context(user_data_dir)
+        self.context_mode = context_mode
+        self.user_data_dir = user_data_dir
+        self._context_closed = False
+        # Ограничение параллельных вкладок одного устройства (automation.tab_concurrency)
+        self.tab_semaphore = asyncio.Semaphore(max(1, tab_concurrency))
         self.playwright = None
         self.browser: Optional[Browser] = None
         self.context: Optional[BrowserContext] = None
@@ -64,7 +102,8 @@ class BotHandler:
             
             # Получаем путь к виртуальному окружению
             project_root = os.path.dirname(os.path.dirname(os.path.dirname(os.path.abspath(__file__))))
-            playwright_cache = os.path.join(project_root, 'python_env', 'playwright-cache')
+            # Кэш выбирает Rust часть (PLAYWRIGHT_BROWSERS_PATH); свой путь - только при запуске без нее
+            playwright_cache = os.environ.get('PLAYWRIGHT_BROWSERS_PATH') or os.path.join(project_root, 'python_env', 'playwright-cache')
             
             # Создаем директорию для кэша если её нет
             os.makedirs(playwright_cache, exist_ok=True)
@@ -124,19 +163,64 @@ class BotHandler:
             logger.error(f"Ошибка при проверке браузера: {e}")
             return False
 
+    @asynccontextmanager
+    async def open_tab(self):
+        """Дополнительная вкладка в контексте устройства; одновременно открыто
+        не больше tab_concurrency вкладок, вкладка закрывается при выходе"""
+        async with self.tab_semaphore:
+            page = await self.context.new_page()
+            try:
+                yield page
+            finally:
+                await page.close()
+
+    async def _launch_browser(self, **launch_options) -> Browser:
+        """Запуск Chromium с ограниченным числом повторов при падении на старте
+        ("Target closed", "browser has crashed" и т.п. под нехваткой памяти)"""
+        for attempt in range(1, BROWSER_LAUNCH_ATTEMPTS + 1):
+            logger.info(f"Запуск браузера, попытка {attempt}/{BROWSER_LAUNCH_ATTEMPTS}")
+            try:
+                return await self.playwright.chromium.launch(**launch_options)
+            except PlaywrightError as e:
+                if attempt == BROWSER_LAUNCH_ATTEMPTS:
+                    raise
+                logger.warning(f"Браузер упал при запуске: {e}. Повтор через {BROWSER_LAUNCH_RETRY_DELAY} сек")
+                await asyncio.sleep(BROWSER_LAUNCH_RETRY_DELAY)
+
+    async def _apply_permission_overrides(self):
+        """Явно выставляет разрешения denied/prompt через CDP Browser.setPermission для всех
+        origin контекста, иначе они остались бы в состоянии браузера по умолчанию"""
+        if not self.permission_overrides:
+            return
+        page_session = await self.context.new_cdp_session(self.page)
+        target_info = (await page_session.send("Target.getTargetInfo"))["targetInfo"]
+        # У persistent контекста нет объекта Browser, команда уходит через сессию страницы
+        session = await self.browser.new_browser_cdp_session() if self.browser else page_session
+        for name, setting in self.permission_overrides.items():
+            params = {"permission": {"name": name}, "setting": setting}
+            if target_info.get("browserContextId"):
+                params["browserContextId"] = target_info["browserContextId"]
+            try:
+                await session.send("Browser.setPermission", params)
+                logger.debug(f"Разрешение {name}: {setting}")
+            except PlaywrightError as e:
+                logger.warning(f"Не удалось выставить разрешение {name}={setting}: {e}")
+
     async def setup_browser(self) -> bool:
         """Инициализация браузера и контекста"""
         try:
-            # Сначала проверяем установку браузера
-            if not await self.check_browser_installation():
+            # Сначала проверяем установку браузера; собственный бинарник (BROWSER_EXECUTABLE) не скачивается
+            executable_path = self.device_launch_options.get("executable_path")
+            if executable_path:
+                logger.info(f"Используется браузер {executable_path}")
+            elif not await self.check_browser_installation():
                 logger.error("Браузер Playwright не установлен или не настроен")
                 return False
 
             self.playwright = await async_playwright().start()
             logger.debug("Playwright успешно инициализирован")
             
-            # Запуск браузера с явным указанием размера окна
-            self.browser = await self.playwright.chromium.launch(
+            launch_options = dict(
                 headless=ENABLE_HEADLESS,
                 args=[
                     f'--window-size={VIEWPORT_WIDTH},{VIEWPORT_HEIGHT}',
@@ -146,33 +230,74 @@ class BotHandler:
                     '--window-position=0,0'
                 ]
             )
-            logger.info(f"Chromium браузер запущен в режиме отображения с размерами: {VIEWPORT_WIDTH}x{VIEWPORT_HEIGHT}")
-    
-            # Создание контекста с эмуляцией устройства
-            self.context = await self.browser.new_context(
-                viewport={"width": VIEWPORT_WIDTH, "height": VIEWPORT_HEIGHT},
-                device_scale_factor=self.device_config['device_scale_factor'],
-                user_agent=self.device_config['user_agent']
-            )
+            if executable_path:
+                launch_options["executable_path"] = executable_path
+            launch_options["args"].extend(self.device_launch_options.get("args", []))
+            if self.device_context_options:
+                # Прокси задается ниже из self.proxy, как и без эмулируемого устройства
+                context_options = {
+                    name: value for name, value in self.device_context_options.items() if name != "proxy"
+                }
+            else:
+                context_options = dict(
+                    viewport={"width": VIEWPORT_WIDTH, "height": VIEWPORT_HEIGHT},
+                    device_scale_factor=self.device_config['device_scale_factor'],
+                    user_agent=self.device_config['user_agent']
+                )
+            viewport = context_options["viewport"]
+            if context_options.get("extra_http_headers"):
+                logger.debug(f"Дополнительные заголовки устройства: {sorted(context_options['extra_http_headers'])}")
+
+            if self.proxy:
+                if self.proxy.get("username"):
+                    # Chromium не принимает учетные данные в --proxy-server, авторизацию выполняет контекст
+                    context_options["proxy"] = self.proxy
+                else:
+                    launch_options["proxy"] = self.proxy
+                logger.info(f"Прокси: {self.proxy['server']}")
+
+            if self.context_mode == "persistent":
+                # Постоянный профиль: браузер и контекст создаются одним вызовом
+                logger.info(f"Запуск браузера с постоянным профилем: {self.user_data_dir}")
+                self.context = await self.playwright.chromium.launch_persistent_context(
+                    self.user_data_dir,
+                    **launch_options,
+                    **context_options,
+                )
+                self.context.on("close", lambda _: setattr(self, "_context_closed", True))
+                logger.info(f"Chromium браузер запущен в режиме отображения с размерами: {VIEWPORT_WIDTH}x{VIEWPORT_HEIGHT}")
+            else:
+                # Запуск браузера с явным указанием размера окна
+                self.browser = await self._launch_browser(**launch_options)
+                logger.info(f"Chromium браузер запущен в режиме отображения с размерами: {VIEWPORT_WIDTH}x{VIEWPORT_HEIGHT}")
+        
+                # Создание контекста с эмуляцией устройства
+                self.context = await self.browser.new_context(**context_options)
+
+            # Init scripts выполняются до скриптов страницы в каждом документе контекста
+            for script in self.init_scripts:
+                await self.context.add_init_script(script=script)
+            if self.init_scripts:
+                logger.debug(f"Добавлено init scripts устройства: {len(self.init_scripts)}")
             
             # Создание страницы
             self.page = await self.context.new_page()
 
+            await self._apply_permission_overrides()
+
             # Установка размера viewport
-            await self.page.set_viewport_size({
-                "width": VIEWPORT_WIDTH,
-                "height": VIEWPORT_HEIGHT
-            })
+            await self.page.set_viewport_size(viewport)
             
-            logger.debug(f"Размеры viewport и окна браузера синхронизированы: {VIEWPORT_WIDTH}x{VIEWPORT_HEIGHT}")
+            logger.debug(f"Размеры viewport синхронизированы: {viewport['width']}x{viewport['height']}")
             
             # Инициализация трейсера
             if ENABLE_TRACING:
-                self.tracer = TracerManager(self.page, self.device_config)
+                self.tracer = TracerManager(self.page, self.device_config, trace_dir=self.output_dir / "tracer")
             
             # Инициализация записи
             if ENABLE_SCREENSHOTS or ENABLE_VIDEO:
                 self.recorder = ScreenRecorder(
+                    output_dir=str(self.output_dir),
                     enable_video=ENABLE_VIDEO,
                     enable_screenshots=ENABLE_SCREENSHOTS
                 )
@@ -408,12 +533,18 @@ class BotHandler:
                     logger.error(f"Ошибка на��игации: {e}")
                     return False
 
+    def _browser_connected(self) -> bool:
+        """Подключен ли браузер; у постоянного контекста нет объекта Browser"""
+        if self.browser:
+            return self.browser.is_connected()
+        return self.context is not None and not self._context_closed
+
     async def check_connection(self) -> bool:
         """Проверка соединения и попытка переподключения"""
         try:
             if self.page.is_closed():
                 # Проверяем, был ли браузер закрыт вручную
-                if self.browser.is_connected() == False:
+                if not self._browser_connected():
                     logger.info("Браузер был закрыт вручную")
                     return False
                 logger.warning("Страница закрыта, требуется переподключение")
@@ -494,6 +625,7 @@ class BotHandler:
 
     async def run(self) -> bool:
         """Основной метод работы"""
+        success = False
         try:
             logger.info("Запуск обработчика WebApp")
             
@@ -526,8 +658,9 @@ class BotHandler:
                 try:
                     if not await self.check_connection():
                         # Проверяем, был ли браузер закрыт вручную
-                        if self.browser.is_connected() == False:
+                        if not self._browser_connected():
                             logger.info("Браузер был закрыт вручную - успешное завершение")
+                            success = True
                             return True
                         logger.warning("Потеряно соединение, завершение работы")
                         break
@@ -547,6 +680,7 @@ class BotHandler:
                     # Проверяем специфические ошибки, связанные с закрытием браузера
                     if "Browser closed" in str(e) or "Target closed" in str(e):
                         logger.info("Браузер был закрыт вручную - успешное завершение")
+                        success = True
                         return True
                     raise
             
@@ -555,6 +689,7 @@ class BotHandler:
             await logic_task
             
             logger.info("Завершение работы обработчика")
+            success = True
             return True
             
         except Exception as e:
@@ -562,13 +697,60 @@ class BotHandler:
             return False
             
         finally:
-            logger.debug("Очистка ресурсов...")
-            await self.cleanup()
+            try:
+                if not success and self.keep_browser_open_on_error and (self.browser or self.context):
+                    # Оставляем браузер открытым для инспекции до подтверждения оператора
+                    await self._wait_for_operator()
+            finally:
+                # Отмена задачи во время ожидания не должна оставлять браузер открытым
+                logger.debug("Очистка ресурсов...")
+                await self.cleanup()
+
+    @staticmethod
+    async def _wait_for_operator():
+        """Ожидает Enter в daemon потоке: при отмене задачи поток, заблокированный в input(),
+        не задерживает завершение event loop (в отличие от executor по умолчанию)"""
+        loop = asyncio.get_running_loop()
+        pressed = loop.create_future()
+
+        def read_stdin():
+            try:
+                input("Браузер оставлен открытым, нажмите Enter для очистки ресурсов...")
+            except EOFError:
+                pass
+            loop.call_soon_threadsafe(lambda: pressed.done() or pressed.set_result(None))
+
+        threading.Thread(target=read_stdin, name="keep-browser-open", daemon=True).start()
+        await pressed
 
-async def handle_webapp(webapp_url: str) -> bool:
+async def handle_webapp(
+    webapp_url: str,
+    tab_concurrency: int = 1,
+    context_mode: str = "incognito",
+    user_data_dir: Optional[str] = None,
+    proxy: Optional[Dict[str, str]] = None,
+    output_dir: Optional[str] = None,
+    launch_options: Optional[Dict[str, Any]] = None,
+    context_options: Optional[Dict[str, Any]] = None,
+    init_scripts: Optional[List[str]] = None,
+    permission_overrides: Optional[Dict[str, str]] = None,
+    keep_browser_open_on_error: bool = False,
+) -> bool:
     """Точка входа для запуска обработчика"""
     try:
-        handler = BotHandler(webapp_url)
+        handler = BotHandler(
+            webapp_url,
+            tab_concurrency=tab_concurrency,
+            context_mode=context_mode,
+            user_data_dir=user_data_dir,
+            proxy=proxy,
+            output_dir=output_dir,
+            launch_options=launch_options,
+            context_options=context_options,
+            init_scripts=init_scripts,
+            permission_overrides=permission_overrides,
+            keep_browser_open_on_error=keep_browser_open_on_error,
+        )
         return await handler.run()
         
     except Exception as e:
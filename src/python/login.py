@@ -16,12 +16,16 @@ import json
 import time
 
 class TelegramLogin:
-    def __init__(self, api_id: int, api_hash: str, phone: str):
+    def __init__(self, api_id: int, api_hash: str, phone: str, session_file: Optional[str] = None):
         self.api_id = api_id
         self.api_hash = api_hash
         self.phone = phone
-        self.session_dir = Path(".py_session")
-        self.session_file = self.session_dir / f"{phone.replace('+', '')}.session"
+        if session_file:
+            self.session_file = Path(session_file)
+            self.session_dir = self.session_file.parent
+        else:
+            self.session_dir = Path(".py_session")
+            self.session_file = self.session_dir / f"{phone.replace('+', '')}.session"
         self.client: Optional[TelegramClient] = None
         self.device_config = None
 
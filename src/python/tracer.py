@@ -9,10 +9,10 @@ from playwright.async_api import Page
 import json
 
 class TracerManager:
-    def __init__(self, page: Page, device_config: Dict[str, Any]):
+    def __init__(self, page: Page, device_config: Dict[str, Any], trace_dir: Optional[Path] = None):
         self.page = page
         self.device_config = device_config
-        self.trace_dir = Path("./recordings/tracer")
+        self.trace_dir = Path(trace_dir) if trace_dir else Path("./recordings/tracer")
         self.current_trace_dir = None
         self.is_tracing = False
         self.visual_interactions = []
@@ -0,0 +1,62 @@
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use log::info;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Корневая директория данных запусков по умолчанию
+const DEFAULT_DATA_DIR: &str = "data";
+
+/// Выходные директории одного запуска. Схема путей:
+///
+/// ```text
+/// <DATA_DIR>/runs/<run_id>/                     общие артефакты запуска
+/// <DATA_DIR>/runs/<run_id>/devices/<device_id>/ записи, скриншоты и трейсы устройства
+/// ```
+///
+/// `run_id` = `<UTC время YYYYmmddTHHMMSSZ>-<pid>`, поэтому параллельные запуски
+/// (в том числе начатые в одну секунду) не пишут в одни и те же файлы
+#[derive(Debug, Clone)]
+pub struct RunContext {
+    pub run_id: String,
+    pub run_dir: PathBuf,
+    device_dirs: HashMap<String, PathBuf>,
+}
+
+impl RunContext {
+    /// Вычисляет пути запуска под `data_dir` и создает директории для всех устройств
+    pub fn create(data_dir: &Path, device_ids: &[String]) -> Result<Self> {
+        let run_id = format!("{}-{}", Utc::now().format("%Y%m%dT%H%M%SZ"), std::process::id());
+        let run_dir = data_dir.join("runs").join(&run_id);
+        fs::create_dir_all(&run_dir)
+            .map_err(|e| anyhow!("Не удалось создать директорию запуска {}: {}", run_dir.display(), e))?;
+
+        let mut device_dirs = HashMap::with_capacity(device_ids.len());
+        for device_id in device_ids {
+            let device_dir = run_dir.join("devices").join(device_id);
+            fs::create_dir_all(&device_dir)?;
+            device_dirs.insert(device_id.clone(), device_dir);
+        }
+
+        info!("Директория запуска: {}", run_dir.display());
+        Ok(Self { run_id, run_dir, device_dirs })
+    }
+
+    /// Как `create`, с корнем из DATA_DIR (по умолчанию `data`)
+    pub fn from_env(device_ids: &[String]) -> Result<Self> {
+        let data_dir = std::env::var("DATA_DIR")
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| DEFAULT_DATA_DIR.to_string());
+        Self::create(Path::new(&data_dir), device_ids)
+    }
+
+    /// Директория устройства; для устройства вне запуска - общая директория запуска
+    pub fn output_dir(&self, device_id: Option<&str>) -> &Path {
+        device_id
+            .and_then(|device_id| self.device_dirs.get(device_id))
+            .unwrap_or(&self.run_dir)
+    }
+}
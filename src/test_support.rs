@@ -0,0 +1,162 @@
+//! Локальный HTTP сервер для герметичных тестов эмуляции.
+//!
+//! Доступен только с фичей `test-server`. Маршруты:
+//! - `/ua` — возвращает User-Agent запроса как text/plain
+//! - `/headers` — возвращает все заголовки запроса в JSON
+//! - любой другой путь — HTML страница, выводящая свойства `navigator` в JSON
+//!   внутри `<pre id="navigator">`
+
+use anyhow::{Result, anyhow};
+use log::{debug, error};
+use serde_json::{Map, Value};
+use std::net::SocketAddr;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+const NAVIGATOR_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta name="viewport" content="width=device-width, initial-scale=1"></head>
+<body>
+<pre id="navigator"></pre>
+<script>
+  const report = {
+    userAgent: navigator.userAgent,
+    platform: navigator.platform,
+    language: navigator.language,
+    languages: navigator.languages,
+    hardwareConcurrency: navigator.hardwareConcurrency,
+    deviceMemory: navigator.deviceMemory,
+    maxTouchPoints: navigator.maxTouchPoints,
+    webdriver: navigator.webdriver,
+    saveData: navigator.connection ? navigator.connection.saveData : null,
+    timezone: Intl.DateTimeFormat().resolvedOptions().timeZone,
+    screen: { width: screen.width, height: screen.height },
+    viewport: { width: window.innerWidth, height: window.innerHeight },
+    devicePixelRatio: window.devicePixelRatio,
+  };
+  document.getElementById("navigator").textContent = JSON.stringify(report);
+</script>
+</body>
+</html>
+"#;
+
+/// Запущенный тестовый сервер. Останавливается при вызове `shutdown` или при drop
+pub struct FixtureServer {
+    addr: SocketAddr,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    handle: JoinHandle<()>,
+}
+
+impl FixtureServer {
+    /// Запускает сервер на случайном свободном порту 127.0.0.1
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (shutdown_tx, mut shutdown_rx) = oneshot::channel::<()>();
+
+        let handle = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown_rx => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, peer)) => {
+                            tokio::spawn(async move {
+                                if let Err(e) = handle_connection(stream).await {
+                                    debug!("Ошибка обработки запроса от {}: {}", peer, e);
+                                }
+                            });
+                        },
+                        Err(e) => {
+                            error!("Ошибка accept тестового сервера: {}", e);
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        debug!("Тестовый сервер запущен на {}", addr);
+        Ok(Self {
+            addr,
+            shutdown_tx: Some(shutdown_tx),
+            handle,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Полный URL для указанного пути, например `server.url("/ua")`
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Останавливает сервер и дожидается завершения цикла accept
+    pub async fn shutdown(mut self) -> Result<()> {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+        (&mut self.handle).await.map_err(|e| anyhow!("Ошибка остановки тестового сервера: {}", e))
+    }
+}
+
+impl Drop for FixtureServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| anyhow!("Некорректная строка запроса: {:?}", request_line))?
+        .to_string();
+
+    let mut headers = Map::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            break;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), Value::String(value.trim().to_string()));
+        }
+    }
+
+    let (content_type, body) = match path.as_str() {
+        "/ua" => {
+            let user_agent = headers
+                .get("user-agent")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            ("text/plain; charset=utf-8", user_agent)
+        },
+        "/headers" => ("application/json", Value::Object(headers).to_string()),
+        _ => ("text/html; charset=utf-8", NAVIGATOR_PAGE.to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    );
+
+    let mut stream = reader.into_inner();
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -1,85 +1,768 @@
 use anyhow::{Result, anyhow};
 use log::{info, error};
-use pyo3::Python;
+use pyo3::{PyErr, PyResult, Python};
+use sha2::{Digest, Sha256};
 use std::fs;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use crate::py_modules::py_imports::get_import_name;
 // use crate::emulation::{get_device_metadata, get_device_browser, EmulatedBrowser};
+use std::borrow::Cow;
 use std::env;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
-// Пытается импортировать пакет с различными вариантами написания имени
-pub fn try_import_package(py: Python<'_>, package: &str) -> Result<()> {
-    // Проверяем специальные случаи импорта
+// Максимальная длина строки вывода в логе по умолчанию
+const DEFAULT_MAX_LOG_LINE_BYTES: usize = 8 * 1024;
+
+// Таймаут импорта одного Python пакета по умолчанию
+const DEFAULT_IMPORT_TIMEOUT_SECS: u64 = 30;
+
+// Интервал обновления heartbeat файла по умолчанию
+const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 30;
+
+/// Полный Python traceback ошибки через модуль `traceback`, включая цепочку
+/// `__cause__`/`__context__`, в том виде, как его печатает интерпретатор
+pub fn python_traceback(py: Python<'_>, err: &PyErr) -> PyResult<String> {
+    let lines: Vec<String> = py.import("traceback")?
+        .getattr("format_exception")?
+        .call1((err.get_type(py), err.value(py), err.traceback(py)))?
+        .extract()?;
+    Ok(lines.concat())
+}
+
+/// Форматирует ошибку Python вместе с многострочным traceback (если его удалось получить).
+/// Каждая строка ограничена `max_log_line_len`
+pub fn format_py_err(py: Python<'_>, err: &PyErr) -> String {
+    let formatted = match python_traceback(py, err) {
+        Ok(traceback) => traceback.trim_end().to_string(),
+        Err(_) => match err.traceback(py).and_then(|traceback| traceback.format().ok()) {
+            Some(traceback) => format!("{}{}", traceback, err),
+            None => err.to_string(),
+        },
+    };
+    truncate_log_lines(&formatted)
+}
+
+/// Максимальная длина строки вывода Python/подпроцессов в логе (MAX_LOG_LINE_BYTES)
+pub fn max_log_line_len() -> usize {
+    env::var("MAX_LOG_LINE_BYTES")
+        .ok()
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(DEFAULT_MAX_LOG_LINE_BYTES)
+}
+
+/// Обрезает строку до `max_log_line_len` байт (по границе символа) с пометкой об обрезке,
+/// чтобы случайно выведенный base64 блоб не раздувал память и лог
+pub fn truncate_log_line(line: &str) -> Cow<'_, str> {
+    let limit = max_log_line_len();
+    if line.len() <= limit {
+        return Cow::Borrowed(line);
+    }
+
+    let mut end = limit;
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!("{}… [обрезано {} байт]", &line[..end], line.len() - end))
+}
+
+/// `truncate_log_line` для каждой строки многострочного вывода
+pub fn truncate_log_lines(output: &str) -> String {
+    output
+        .lines()
+        .map(truncate_log_line)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Выполняет замыкание под GIL, переводя ошибки pyo3 в anyhow с Python traceback
+pub fn with_python<T>(f: impl FnOnce(Python<'_>) -> PyResult<T>) -> Result<T> {
+    Python::with_gil(|py| f(py).map_err(|e| anyhow!("Python error: {}", format_py_err(py, &e))))
+}
+
+/// Как `with_python`, но в blocking потоке tokio, чтобы работа под GIL не блокировала async runtime
+pub async fn run_python<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(Python<'_>) -> PyResult<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || with_python(f)).await?
+}
+
+/// Таймаут импорта пакета из IMPORT_TIMEOUT_SECS
+pub fn import_timeout() -> Duration {
+    let secs = env::var("IMPORT_TIMEOUT_SECS")
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .unwrap_or(DEFAULT_IMPORT_TIMEOUT_SECS);
+    Duration::from_secs(secs)
+}
+
+/// `try_import_package` в отдельном потоке с ограничением по времени: зависший импорт
+/// (например, проблема загрузки крипто-бэкенда telethon) превращается в ошибку.
+/// Зависший поток остается висеть, поэтому после таймаута Python использовать нельзя.
+/// Вызывать без удерживаемого GIL
+pub fn import_with_timeout(package: &str, timeout: Duration) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let thread_package = package.to_string();
+    thread::Builder::new()
+        .name(format!("import-{}", package))
+        .spawn(move || {
+            let result = Python::with_gil(|py| try_import_package(py, &thread_package));
+            let _ = tx.send(result);
+        })?;
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(ImportTimeout {
+            package: package.to_string(),
+            timeout,
+        }.into()),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(anyhow!("Поток импорта {} завершился аварийно", package)),
+    }
+}
+
+/// Импорт пакета не завершился за отведенное время
+#[derive(Debug, thiserror::Error)]
+#[error("import of {package} timed out after {}s", timeout.as_secs())]
+pub struct ImportTimeout {
+    pub package: String,
+    pub timeout: Duration,
+}
+
+/// Имена, под которыми пробуется импорт пакета: имя из карты исключений, само имя пакета и
+/// вариант с подчеркиваниями, без повторов. Составное имя (`opentelemetry.sdk`) задано явно,
+/// поэтому для него остальные варианты не имеют смысла и не пробуются
+fn import_candidates(package: &str) -> Vec<String> {
     let import_name = get_import_name(package);
-    if py.import(import_name).is_ok() {
-        return Ok(());
+    if import_name.contains('.') {
+        return vec![import_name.to_string()];
     }
 
-    // Пробуем стандартный вариант
-    if py.import(package).is_ok() {
-        return Ok(());
+    let mut candidates = Vec::with_capacity(3);
+    for candidate in [import_name.to_string(), package.to_string(), package.replace('-', "_")] {
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
     }
+    candidates
+}
 
-    // Пробуем вариант с подчеркиваниями
-    let underscore_name = package.replace('-', "_");
-    if underscore_name != package && py.import(&*underscore_name).is_ok() {
-        return Ok(());
+// Пытается импортировать пакет с различными вариантами написания имени
+pub fn try_import_package(py: Python<'_>, package: &str) -> Result<()> {
+    let candidates = import_candidates(package);
+    let mut first_error = None;
+    for candidate in &candidates {
+        match py.import(candidate.as_str()) {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
     }
 
-    // Если все попытки не удались, возвращаем ошибку
+    // Если все попытки не удались, возвращаем ошибку основного варианта имени
     Err(anyhow!(
-        "Не удалось импортировать пакет '{}' (пробовал варианты: {}, {}, {})",
-        package,
-        import_name,
+        "Не удалось импортировать пакет '{}' (пробовал варианты: {}): {}",
         package,
-        underscore_name
+        candidates.join(", "),
+        first_error.map(|e| e.to_string()).unwrap_or_default()
     ))
 }
 
-/// Парсит файл requirements.txt и возвращает список пакетов
-pub fn parse_requirements() -> Result<Vec<String>> {
+/// Импортирует все пакеты одним захватом GIL в отдельном потоке и возвращает неудавшиеся
+/// с ошибками. Результаты приходят по мере импорта, поэтому `timeout` ограничивает каждый пакет
+/// отдельно, а зависший импорт возвращается как `ImportTimeout` для конкретного пакета
+/// (как и в `import_with_timeout`, после этого Python использовать нельзя). Вызывать без удерживаемого GIL
+pub fn import_all_with_timeout(packages: &[String], timeout: Duration) -> Result<Vec<(String, anyhow::Error)>> {
+    let (tx, rx) = mpsc::channel();
+    let thread_packages = packages.to_vec();
+    thread::Builder::new()
+        .name("import-batch".to_string())
+        .spawn(move || {
+            Python::with_gil(|py| {
+                for package in &thread_packages {
+                    if tx.send(try_import_package(py, package)).is_err() {
+                        break;
+                    }
+                }
+            });
+        })?;
+
+    let mut failures = Vec::new();
+    for package in packages {
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => failures.push((package.clone(), e)),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                return Err(ImportTimeout { package: package.clone(), timeout }.into());
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("Поток импорта завершился аварийно на пакете {}", package));
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// Убирает комментарий из строки requirements так же, как pip: строка, начинающаяся с `#`,
+/// целиком комментарий, иначе комментарием считается `#` после пробельного символа
+pub fn strip_requirement_comment(line: &str) -> &str {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return "";
+    }
+    let end = line
+        .char_indices()
+        .find(|&(index, c)| c == '#' && line[..index].ends_with(char::is_whitespace))
+        .map_or(line.len(), |(index, _)| index);
+    line[..end].trim_end()
+}
+
+/// Требование из requirements.txt: `uvicorn[standard]>=0.20; python_version>='3.8'`
+/// -> name `uvicorn`, extras `[standard]`, version_spec `>=0.20`, marker `python_version>='3.8'`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    pub name: String,
+    pub extras: Vec<String>,
+    pub version_spec: String,
+    pub marker: Option<String>,
+    pub source: RequirementSource,
+}
+
+/// Откуда pip устанавливает требование
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequirementSource {
+    /// Пакет из индекса (PyPI)
+    Index,
+    /// `-e <путь или VCS URL>`
+    Editable(String),
+    /// VCS или прямая ссылка: `git+https://...` или `name @ https://...`
+    Direct(String),
+}
+
+impl Requirement {
+    /// Разбирает строку requirements; `None` для пустых строк, комментариев и опций pip
+    /// (`-r` обрабатывает `parse_requirements_file`)
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = strip_requirement_comment(line);
+        if let Some(target) = line.strip_prefix("--editable").or_else(|| line.strip_prefix("-e")) {
+            let target = target.trim_start_matches('=').trim();
+            return Self::direct(target, RequirementSource::Editable(target.to_string()));
+        }
+        if line.starts_with('-') {
+            return None;
+        }
+        if let Some((name, target)) = line.split_once(" @ ") {
+            let mut requirement = Self::parse(name)?;
+            requirement.source = RequirementSource::Direct(target.trim().to_string());
+            return Some(requirement);
+        }
+        if line.contains("://") {
+            return Self::direct(line, RequirementSource::Direct(line.to_string()));
+        }
+
+        let (requirement, marker) = match line.split_once(';') {
+            Some((requirement, marker)) => (requirement.trim(), Some(marker.trim().to_string())),
+            None => (line, None),
+        };
+
+        let name_end = requirement
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+            .unwrap_or(requirement.len());
+        let name = requirement[..name_end].to_string();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut rest = requirement[name_end..].trim_start();
+        let mut extras = Vec::new();
+        if let Some(bracketed) = rest.strip_prefix('[') {
+            let (list, after) = bracketed.split_once(']').unwrap_or((bracketed, ""));
+            extras = list
+                .split(',')
+                .map(str::trim)
+                .filter(|extra| !extra.is_empty())
+                .map(String::from)
+                .collect();
+            rest = after;
+        }
+
+        Some(Self {
+            name,
+            extras,
+            version_spec: rest.split_whitespace().collect(),
+            marker: marker.filter(|marker| !marker.is_empty()),
+            source: RequirementSource::Index,
+        })
+    }
+
+    /// Editable или VCS требование. Имя берется из `#egg=name`, иначе из последнего
+    /// сегмента пути без `.git` и `@ref`
+    fn direct(target: &str, source: RequirementSource) -> Option<Self> {
+        let name = match target.split_once("#egg=") {
+            Some((_, egg)) => egg.split('&').next().unwrap_or_default(),
+            None => {
+                let path = target.split(['#', '?']).next().unwrap_or_default().trim_end_matches('/');
+                let segment = path.rsplit('/').next().unwrap_or_default();
+                let segment = segment.split('@').next().unwrap_or_default();
+                segment.strip_suffix(".git").unwrap_or(segment)
+            }
+        };
+        if name.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: name.to_string(),
+            extras: Vec::new(),
+            version_spec: String::new(),
+            marker: None,
+            source,
+        })
+    }
+
+    /// Пакет из индекса. Editable и VCS установки не импортируются при проверке окружения:
+    /// имя модуля у них не обязано совпадать с именем из requirements
+    pub fn is_importable(&self) -> bool {
+        self.source == RequirementSource::Index
+    }
+
+    /// Строка для pip install с extras и версией, без маркеров
+    pub fn spec(&self) -> String {
+        match &self.source {
+            RequirementSource::Index => {}
+            RequirementSource::Editable(target) | RequirementSource::Direct(target) => return target.clone(),
+        }
+        let extras = if self.extras.is_empty() {
+            String::new()
+        } else {
+            format!("[{}]", self.extras.join(","))
+        };
+        format!("{}{}{}", self.name, extras, self.version_spec)
+    }
+
+    /// Применимо ли требование к интерпретатору `python_version` (major, minor). Вычисляются
+    /// только условия `python_version <op> 'X.Y'`, объединенные через `and`; маркеры с другими
+    /// переменными или `or` считаются выполненными, решение остается за pip
+    pub fn applies_to(&self, python_version: (u32, u32)) -> bool {
+        let Some(marker) = &self.marker else {
+            return true;
+        };
+        if marker.contains(" or ") {
+            return true;
+        }
+        marker
+            .split(" and ")
+            .filter_map(parse_python_version_clause)
+            .all(|(op, version)| match op {
+                "==" => python_version == version,
+                "!=" => python_version != version,
+                "<" => python_version < version,
+                "<=" => python_version <= version,
+                ">" => python_version > version,
+                ">=" => python_version >= version,
+                _ => true,
+            })
+    }
+}
+
+/// Разбирает условие `python_version >= '3.8'`; `None` для других переменных маркера
+fn parse_python_version_clause(clause: &str) -> Option<(&'static str, (u32, u32))> {
+    let rest = clause.trim().strip_prefix("python_version")?.trim_start();
+    let op = ["==", "!=", "<=", ">=", "<", ">"].into_iter().find(|op| rest.starts_with(op))?;
+    let value = rest[op.len()..].trim().trim_matches(|c| c == '\'' || c == '"');
+    let (major, minor) = value.split_once('.')?;
+    Some((op, (major.parse().ok()?, minor.parse().ok()?)))
+}
+
+/// Парсит файл requirements вместе с включениями `-r`/`--requirement` (пути относительно
+/// включающего файла). Повторное включение файла игнорируется, чтобы циклы не зацикливали разбор
+pub fn parse_requirements_file(path: &Path) -> Result<Vec<Requirement>> {
+    let mut requirements = Vec::new();
+    collect_requirements(path, &mut HashSet::new(), &mut requirements, &mut Vec::new())?;
+    Ok(requirements)
+}
+
+/// SHA-256 (hex) содержимого файла requirements и всех его включений `-r` в порядке разбора
+pub fn requirements_hash(path: &Path) -> Result<String> {
+    let mut contents = Vec::new();
+    collect_requirements(path, &mut HashSet::new(), &mut Vec::new(), &mut contents)?;
+
+    let mut hasher = Sha256::new();
+    for content in &contents {
+        hasher.update(content.as_bytes());
+        // Разделитель, чтобы перенос строки между файлами не давал тот же хэш
+        hasher.update([0u8]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn collect_requirements(
+    path: &Path,
+    visited: &mut HashSet<PathBuf>,
+    requirements: &mut Vec<Requirement>,
+    contents: &mut Vec<String>,
+) -> Result<()> {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Не удалось прочитать {}: {}", path.display(), e))?;
+
+    for line in content.lines() {
+        let line = strip_requirement_comment(line);
+        let include = line
+            .strip_prefix("--requirement")
+            .or_else(|| line.strip_prefix("-r"))
+            .map(|rest| rest.trim_start_matches('=').trim());
+        match include {
+            Some(include) => {
+                let include_path = path.parent().unwrap_or(Path::new("")).join(include);
+                collect_requirements(&include_path, visited, requirements, contents)?;
+            }
+            None => requirements.extend(Requirement::parse(line)),
+        }
+    }
+    contents.push(content);
+    Ok(())
+}
+
+/// Парсит файл requirements.txt; требования, исключенные маркером `python_version`
+/// для текущего интерпретатора, пропускаются
+pub fn parse_requirements() -> Result<Vec<Requirement>> {
     info!("Парсинг requirements.txt...");
-    let requirements = fs::read_to_string("requirements.txt")?;
+    let requirements = parse_requirements_file(Path::new("requirements.txt"))?;
+    let python_version = Python::with_gil(|py| {
+        let version = py.version_info();
+        (version.major as u32, version.minor as u32)
+    });
     Ok(requirements
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .map(|line| {
-            line.split(['=', '>', '<', '~'])
-                .next()
-                .unwrap_or("")
-                .trim()
-                .to_string()
+        .into_iter()
+        .filter(|requirement| {
+            let applies = requirement.applies_to(python_version);
+            if !applies {
+                info!(
+                    "Пакет {} пропущен: маркер '{}' не выполняется для Python {}.{}",
+                    requirement.name,
+                    requirement.marker.as_deref().unwrap_or_default(),
+                    python_version.0,
+                    python_version.1
+                );
+            }
+            applies
         })
-        .filter(|pkg| !pkg.is_empty())
         .collect())
 }
 
-/// Удаляет директории logs и recordings если они существуют
-pub fn delete_logs() -> Result<()> {
-    let should_delete = env::var("DELETE_LOG_FIRST_START")
-        .unwrap_or_else(|_| "false".to_string())
-        .parse::<bool>()
-        .unwrap_or(false);
+/// Читает булев флаг из переменной окружения, при отсутствии или ошибке парсинга возвращает `default`
+pub fn env_flag(name: &str, default: bool) -> bool {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.trim().to_lowercase().parse::<bool>().ok())
+        .unwrap_or(default)
+}
 
-    if !should_delete {
-        info!("Пропуск удаления логов (DELETE_LOG_FIRST_START=false)");
-        return Ok(());
+/// Путь к heartbeat файлу из HEARTBEAT_FILE, если он задан
+pub fn heartbeat_path() -> Option<PathBuf> {
+    env::var("HEARTBEAT_FILE")
+        .ok()
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Интервал обновления heartbeat файла в секундах (HEARTBEAT_INTERVAL_SECS)
+pub fn heartbeat_interval_secs() -> u64 {
+    env::var("HEARTBEAT_INTERVAL_SECS")
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL_SECS)
+}
+
+/// Проверяет путь heartbeat файла и создает его директорию перед запуском автоматизации
+pub fn prepare_heartbeat_file() -> Result<Option<PathBuf>> {
+    let Some(path) = heartbeat_path() else {
+        return Ok(None);
+    };
+
+    if path.is_dir() {
+        return Err(anyhow!("HEARTBEAT_FILE указывает на директорию: {}", path.display()));
+    }
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
     }
 
-    let current_dir = std::env::current_dir()?;
-    let dirs_to_delete = ["logs", "recordings"];
+    info!("Heartbeat файл: {} (интервал {}с)", path.display(), heartbeat_interval_secs());
+    Ok(Some(path))
+}
 
-    for dir in dirs_to_delete.iter() {
-        let path = current_dir.join(dir);
+/// Удаляет heartbeat файл при завершении работы, чтобы watchdog не видел устаревший пульс
+pub fn remove_heartbeat_file() -> Result<()> {
+    if let Some(path) = heartbeat_path() {
         if path.exists() {
-            info!("Удаление директории: {}", path.display());
-            match fs::remove_dir_all(&path) {
-                Ok(_) => info!("Успешно удалена директория: {}", dir),
-                Err(e) => error!("Ошибка при удалении директории {}: {}", dir, e)
-            }
-        } else {
+            fs::remove_file(&path)?;
+            info!("Heartbeat файл удален: {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Удаляет директории `dirs` (`[logging] delete_dirs`, по умолчанию logs и recordings) если
+/// `should_delete` (`[logging] delete_on_start` или DELETE_LOG_FIRST_START). Пути проверяются
+/// до удаления: только относительные поддиректории текущей директории проекта, без `..` и
+/// символических ссылок; при любом нарушении ничего не удаляется. Возвращает удаленные пути
+pub fn delete_logs(should_delete: bool, dirs: &[String]) -> Result<Vec<PathBuf>> {
+    if !should_delete {
+        info!("Пропуск удаления логов (delete_on_start=false)");
+        return Ok(Vec::new());
+    }
+
+    delete_project_dirs(&std::env::current_dir()?, dirs)
+}
+
+/// Удаление для `delete_logs` относительно явного корня проекта
+fn delete_project_dirs(project_root: &Path, dirs: &[String]) -> Result<Vec<PathBuf>> {
+    let mut dirs_to_delete = Vec::new();
+    for dir in dirs {
+        if let Some(path) = checked_project_subdir(project_root, dir)? {
+            dirs_to_delete.push(path);
+        }
+    }
+
+    let mut deleted = Vec::new();
+    for path in dirs_to_delete {
+        info!("Удаление директории: {}", path.display());
+        match fs::remove_dir_all(&path) {
+            Ok(_) => deleted.push(path),
+            Err(e) => error!("Ошибка при удалении директории {}: {}", path.display(), e)
+        }
+    }
+
+    Ok(deleted)
+}
+
+/// Проверяет, что `dir` - существующая поддиректория `project_root`, которую безопасно удалять.
+/// Отсутствующая директория - `None`; абсолютный путь, `..`, символическая ссылка, файл
+/// или путь вне проекта - ошибка
+fn checked_project_subdir(project_root: &Path, dir: &str) -> Result<Option<PathBuf>> {
+    let relative = Path::new(dir.trim());
+    let is_plain_relative = !relative.as_os_str().is_empty()
+        && relative.components().all(|component| matches!(component, std::path::Component::Normal(_)));
+    if !is_plain_relative {
+        return Err(anyhow!("Отказ удалять '{}': допустим только относительный путь внутри проекта без '..'", dir));
+    }
+
+    let path = project_root.join(relative);
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
             info!("Директория {} не найдена, пропуск", dir);
+            return Ok(None);
         }
+        Err(e) => return Err(anyhow!("Не удалось проверить {}: {}", path.display(), e)),
+    };
+    if metadata.file_type().is_symlink() {
+        return Err(anyhow!("Отказ удалять {}: это символическая ссылка", path.display()));
+    }
+    if !metadata.is_dir() {
+        return Err(anyhow!("Отказ удалять {}: это не директория", path.display()));
     }
 
+    // Символическая ссылка в промежуточном компоненте тоже может увести за пределы проекта
+    let canonical_root = project_root.canonicalize()?;
+    let canonical = path.canonicalize()?;
+    if canonical == canonical_root || !canonical.starts_with(&canonical_root) {
+        return Err(anyhow!("Отказ удалять {}: путь вне директории проекта {}", canonical.display(), canonical_root.display()));
+    }
+    Ok(Some(path))
+}
+
+// Суффикс архивного файла лога: `bot.log` -> `bot.log.20261016T120000Z`
+const LOG_ARCHIVE_SUFFIX_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Ротация директорий `dirs` (`[logging] delete_dirs`) вместо полного удаления: текущие файлы
+/// переименовываются с суффиксом времени запуска, архивные старше `max_age_days` удаляются, затем
+/// самые старые архивные удаляются, пока общий размер директорий больше `max_total_bytes`
+/// (0 - без ограничения). Пути проверяются как в `delete_logs`, символические ссылки внутри
+/// пропускаются. Файл лога бота и архивы ошибок не трогаются: их ротируют `logging` и
+/// `failure_bundle`. Возвращает число удаленных файлов
+pub fn rotate_logs(dirs: &[String], max_age_days: u64, max_total_bytes: u64) -> Result<usize> {
+    let project_root = std::env::current_dir()?;
+    let mut dirs_to_rotate = Vec::new();
+    for dir in dirs {
+        if let Some(path) = checked_project_subdir(&project_root, dir)? {
+            dirs_to_rotate.push(path);
+        }
+    }
+
+    let suffix = chrono::Utc::now().format(LOG_ARCHIVE_SUFFIX_FORMAT).to_string();
+    let max_age = Duration::from_secs(max_age_days.saturating_mul(24 * 60 * 60));
+    let now = std::time::SystemTime::now();
+
+    let mut archived = Vec::new();
+    let mut total_bytes = 0u64;
+    for dir in dirs_to_rotate {
+        let mut files = Vec::new();
+        collect_files(&dir, &mut files)?;
+        for path in files.into_iter().filter(|path| !is_rotated_elsewhere(path)) {
+            let path = if is_archived_log(&path) {
+                path
+            } else {
+                let mut name = path.file_name().unwrap_or_default().to_os_string();
+                name.push(format!(".{}", suffix));
+                let target = path.with_file_name(name);
+                fs::rename(&path, &target)
+                    .map_err(|e| anyhow!("Не удалось переименовать {}: {}", path.display(), e))?;
+                target
+            };
+            let metadata = fs::metadata(&path)?;
+            total_bytes += metadata.len();
+            archived.push((metadata.modified()?, metadata.len(), path));
+        }
+    }
+
+    // Сначала самые старые: они удаляются первыми и по возрасту, и по размеру
+    archived.sort_by_key(|(modified, _, _)| *modified);
+    let mut removed = 0;
+    for (modified, len, path) in archived {
+        let expired = now.duration_since(modified).is_ok_and(|age| age > max_age);
+        let over_limit = max_total_bytes > 0 && total_bytes > max_total_bytes;
+        if !expired && !over_limit {
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(_) => {
+                total_bytes = total_bytes.saturating_sub(len);
+                removed += 1;
+            }
+            Err(e) => error!("Ошибка при удалении {}: {}", path.display(), e),
+        }
+    }
+
+    info!(
+        "Ротация логов: удалено файлов {}, осталось {} байт (max_age_days={}, max_total_bytes={})",
+        removed, total_bytes, max_age_days, max_total_bytes
+    );
+    Ok(removed)
+}
+
+/// Уже переименованный ротацией файл: последнее расширение - время в формате суффикса
+fn is_archived_log(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| chrono::NaiveDateTime::parse_from_str(ext, LOG_ARCHIVE_SUFFIX_FORMAT).is_ok())
+}
+
+/// Файлы с собственной ротацией: `bombie-bot.log` с копиями `.1`, `.2`, ... и архивы `failure-*.zip`
+fn is_rotated_elsewhere(path: &Path) -> bool {
+    let is_bot_log = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .is_some_and(|name| name.starts_with(crate::logging::LOG_FILE_NAME));
+    is_bot_log || crate::failure_bundle::is_bundle_file_name(path)
+}
+
+/// Рекурсивно собирает файлы директории без перехода по символическим ссылкам
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            continue;
+        }
+        if file_type.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else {
+            files.push(entry.path());
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Временная директория теста, удаляется при выходе из области видимости
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("bombie-bot-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn delete_logs_refuses_symlinked_dir_and_deletes_nothing() {
+        let temp = TempDir::new("delete-logs-symlink");
+        let project = temp.0.join("project");
+        let outside = temp.0.join("outside");
+        fs::create_dir_all(project.join("recordings")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("keep.txt"), "keep").unwrap();
+        fs::write(project.join("recordings").join("video.webm"), "video").unwrap();
+        std::os::unix::fs::symlink(&outside, project.join("logs")).unwrap();
+
+        let dirs = vec!["recordings".to_string(), "logs".to_string()];
+        assert!(delete_project_dirs(&project, &dirs).is_err());
+        assert!(outside.join("keep.txt").exists());
+        assert!(project.join("recordings").join("video.webm").exists());
+    }
+
+    #[test]
+    fn import_candidates_start_with_mapped_name() {
+        assert_eq!(import_candidates("beautifulsoup4"), ["bs4", "beautifulsoup4"]);
+        assert_eq!(import_candidates("python-dotenv"), ["dotenv", "python-dotenv", "python_dotenv"]);
+    }
+
+    #[test]
+    fn import_candidates_keep_only_dotted_mapped_name() {
+        assert_eq!(import_candidates("opentelemetry-sdk"), ["opentelemetry.sdk"]);
+    }
+
+    #[test]
+    fn parse_requirements_file_handles_comments_includes_and_direct_sources() {
+        let temp = TempDir::new("parse-requirements");
+        fs::write(temp.0.join("base.txt"), "requests==2.31.0  # HTTP клиент\n").unwrap();
+        fs::write(
+            temp.0.join("requirements.txt"),
+            "# зависимости бота\n\
+             -r base.txt\n\
+             uvicorn[standard]>=0.20 ; python_version >= '3.8'  # сервер\n\
+             -e ./vendor/helper\n\
+             git+https://github.com/example/tgtools.git@v1.2#egg=tgtools\n",
+        )
+        .unwrap();
+
+        let requirements = parse_requirements_file(&temp.0.join("requirements.txt")).unwrap();
+        let names: Vec<&str> = requirements.iter().map(|requirement| requirement.name.as_str()).collect();
+        assert_eq!(names, ["requests", "uvicorn", "helper", "tgtools"]);
+
+        assert_eq!(requirements[0].version_spec, "==2.31.0");
+        assert_eq!(requirements[1].extras, ["standard"]);
+        assert_eq!(requirements[1].version_spec, ">=0.20");
+        assert_eq!(requirements[1].marker.as_deref(), Some("python_version >= '3.8'"));
+        assert_eq!(requirements[2].source, RequirementSource::Editable("./vendor/helper".to_string()));
+        assert_eq!(
+            requirements[3].source,
+            RequirementSource::Direct("git+https://github.com/example/tgtools.git@v1.2#egg=tgtools".to_string())
+        );
+        assert!(requirements[0].is_importable() && requirements[1].is_importable());
+        assert!(!requirements[2].is_importable() && !requirements[3].is_importable());
+    }
+}
@@ -0,0 +1,31 @@
+//! Проверка тестового сервера эмуляции: `/ua` возвращает User-Agent запроса.
+//! Запуск: `cargo test --features test-server`
+#![cfg(feature = "test-server")]
+
+use bombie_bot::emulation::EmulatedDeviceBuilder;
+use bombie_bot::test_support::FixtureServer;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn ua_route_echoes_device_user_agent() {
+    let device = EmulatedDeviceBuilder::new("fixture-android").build().unwrap();
+    let server = FixtureServer::start().await.unwrap();
+
+    let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+    let request = format!(
+        "GET /ua HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}\r\nConnection: close\r\n\r\n",
+        server.addr(),
+        device.browser.user_agent()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK"));
+    assert!(head.contains("Content-Type: text/plain"));
+    assert_eq!(body, device.browser.user_agent());
+
+    server.shutdown().await.unwrap();
+}